@@ -14,7 +14,7 @@ impl ClientTcp {
     }
 
     pub async fn get_id_all_devices(&mut self) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/devices-list", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/devices", self.url))
             .await?
             .text()
             .await?;
@@ -22,7 +22,7 @@ impl ClientTcp {
     }
 
     pub async fn get_id_all_rooms(&mut self) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/rooms-list", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/rooms", self.url))
             .await?
             .text()
             .await?;
@@ -30,7 +30,7 @@ impl ClientTcp {
     }
 
     pub async fn get_id_all_houses(&mut self) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/house-list", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/houses", self.url))
             .await?
             .text()
             .await?;
@@ -38,7 +38,15 @@ impl ClientTcp {
     }
 
     pub async fn get_device_var(&mut self, device_uid: &str) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/device/{device_uid}/var", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/device/{device_uid}/var", self.url))
+            .await?
+            .text()
+            .await?;
+        Ok(resp)
+    }
+
+    pub async fn get_device_readings(&mut self, device_uid: &str) -> Result<String, Error> {
+        let resp = reqwest::get(format!("{0}/api/v1/device/{device_uid}/readings", self.url))
             .await?
             .text()
             .await?;
@@ -60,7 +68,7 @@ impl ClientTcp {
 
         let client = reqwest::Client::new();
         let res = client
-            .post(format!("{0}/device", self.url))
+            .post(format!("{0}/api/v1/device", self.url))
             .json(&map)
             .send()
             .await?
@@ -77,7 +85,7 @@ impl ClientTcp {
 
         let client = reqwest::Client::new();
         let res = client
-            .post(format!("{0}/room", self.url))
+            .post(format!("{0}/api/v1/room", self.url))
             .json(&map)
             .send()
             .await?
@@ -93,7 +101,7 @@ impl ClientTcp {
 
         let client = reqwest::Client::new();
         let res = client
-            .post(format!("{0}/house", self.url))
+            .post(format!("{0}/api/v1/house", self.url))
             .json(&map)
             .send()
             .await?
@@ -104,7 +112,7 @@ impl ClientTcp {
     }
 
     pub async fn get_list_of_houses(&mut self) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/house-list", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/houses", self.url))
             .await?
             .text()
             .await?;
@@ -112,14 +120,22 @@ impl ClientTcp {
     }
 
     pub async fn get_list_of_rooms(&mut self, house_uid: &str) -> Result<String, Error> {
-        let u = format!("{0}/house/{house_uid}/list", self.url);
+        let u = format!("{0}/api/v1/house/{house_uid}/rooms", self.url);
         let resp = reqwest::get(u).await?.text().await?;
         Ok(resp)
     }
 
     pub async fn get_list_of_devices(&mut self, room_uid: &str) -> Result<String, Error> {
-        println!("{0}/room/{1}/list", self.url, room_uid);
-        let resp = reqwest::get(format!("{0}/room/{1}/list", self.url, room_uid))
+        let resp = reqwest::get(format!("{0}/api/v1/room/{1}/devices", self.url, room_uid))
+            .await?
+            .text()
+            .await?;
+        Ok(resp)
+    }
+
+    /// House with its rooms and their devices.
+    pub async fn get_house_tree(&mut self, house_uid: &str) -> Result<String, Error> {
+        let resp = reqwest::get(format!("{0}/api/v1/house/{house_uid}/tree", self.url))
             .await?
             .text()
             .await?;
@@ -127,15 +143,32 @@ impl ClientTcp {
     }
 
     pub async fn get_full_report(&mut self, house_uid: &str) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/report/{house_uid}", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/report/{house_uid}", self.url))
             .await?
             .text()
             .await?;
         Ok(resp)
     }
 
+    /// Energy used by the sockets of a house between two Unix timestamps.
+    pub async fn get_energy_report(
+        &mut self,
+        house_uid: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<String, Error> {
+        let resp = reqwest::get(format!(
+            "{0}/api/v1/house/{house_uid}/energy?from={from}&to={to}",
+            self.url
+        ))
+        .await?
+        .text()
+        .await?;
+        Ok(resp)
+    }
+
     pub async fn get_device_description(&mut self, device_uid: &str) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/device/{device_uid}", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/device/{device_uid}", self.url))
             .await?
             .text()
             .await?;
@@ -143,7 +176,7 @@ impl ClientTcp {
     }
 
     pub async fn get_device_by_id(&mut self, device_uid: &str) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/device/{device_uid}", self.url))
+        let resp = reqwest::get(format!("{0}/api/v1/device/{device_uid}", self.url))
             .await?
             .text()
             .await?;
@@ -151,7 +184,9 @@ impl ClientTcp {
     }
 
     pub async fn remove_device_by_id(&mut self, device_uid: &str) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/device/{device_uid}/remove", self.url))
+        let resp = reqwest::Client::new()
+            .delete(format!("{0}/api/v1/device/{device_uid}", self.url))
+            .send()
             .await?
             .text()
             .await?;
@@ -159,7 +194,9 @@ impl ClientTcp {
     }
 
     pub async fn remove_room_by_id(&mut self, room_uid: &str) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/room/{room_uid}/remove", self.url))
+        let resp = reqwest::Client::new()
+            .delete(format!("{0}/api/v1/room/{room_uid}", self.url))
+            .send()
             .await?
             .text()
             .await?;
@@ -167,13 +204,58 @@ impl ClientTcp {
     }
 
     pub async fn remove_house_by_id(&mut self, house_uid: &str) -> Result<String, Error> {
-        let resp = reqwest::get(format!("{0}/house/{house_uid}/remove", self.url))
+        let resp = reqwest::Client::new()
+            .delete(format!("{0}/api/v1/house/{house_uid}", self.url))
+            .send()
+            .await?
+            .text()
+            .await?;
+        Ok(resp)
+    }
+
+    pub async fn get_trash(&mut self) -> Result<String, Error> {
+        let resp = reqwest::get(format!("{0}/api/v1/trash", self.url))
+            .await?
+            .text()
+            .await?;
+        Ok(resp)
+    }
+
+    /// Audit log of the changes made to a house, its rooms and devices.
+    pub async fn get_house_events(&mut self, house_uid: &str) -> Result<String, Error> {
+        let resp = reqwest::get(format!("{0}/api/v1/events?house={house_uid}", self.url))
             .await?
             .text()
             .await?;
         Ok(resp)
     }
 
+    /// Restores a deleted entity; `kind` is `house`, `room` or `device`.
+    pub async fn restore(&mut self, kind: &str, uid: &str) -> Result<String, Error> {
+        let resp = reqwest::Client::new()
+            .post(format!("{0}/api/v1/{kind}/{uid}/restore", self.url))
+            .send()
+            .await?
+            .text()
+            .await?;
+        Ok(resp)
+    }
+
+    pub async fn set_state(&mut self, dev_id: &str, state: bool) -> Result<String, Error> {
+        let mut map = HashMap::new();
+        map.insert("state", state);
+
+        let resp = reqwest::Client::new()
+            .put(format!("{0}/api/v1/device/{1}/state", self.url, dev_id))
+            .json(&map)
+            .send()
+            .await?
+            .text()
+            .await?;
+        Ok(resp)
+    }
+
+    /// Toggles the device through the deprecated GET route; prefer `set_state`.
     pub async fn change_state(&mut self, dev_id: &str) -> Result<String, Error> {
         let resp = reqwest::get(format!("{0}/device/{1}/state", self.url, dev_id))
             .await?
@@ -183,53 +265,45 @@ impl ClientTcp {
     }
 }
 
+/// First device known to the local server.
+fn first_device() -> serde_json::Value {
+    let devices: Vec<serde_json::Value> =
+        reqwest::blocking::get("http://127.0.0.1:8080/api/v1/devices?limit=1")
+            .unwrap()
+            .json()
+            .unwrap();
+
+    devices.into_iter().next().unwrap_or_default()
+}
+
 #[no_mangle]
 pub extern "C" fn get_device_description() -> *mut i8 {
-    let dev_id = reqwest::blocking::get("http://127.0.0.1:8080/devices-list")
-        .unwrap()
-        .text()
-        .unwrap();
-    let id = dev_id.split(' ').collect::<Vec<&str>>()[0];
-
-    let device_description = reqwest::blocking::get(format!(
-        "{0}/device/{1}",
-        "http://127.0.0.1:8080",
-        id.trim_start_matches('"')
-    ))
-    .unwrap()
-    .text()
-    .unwrap();
+    let device_description = first_device().to_string();
 
     CString::new(device_description).unwrap().into_raw()
 }
 
 #[no_mangle]
 pub extern "C" fn set_device_state() -> *mut i8 {
-    let dev_id = reqwest::blocking::get("http://127.0.0.1:8080/devices-list")
-        .unwrap()
-        .text()
-        .unwrap();
-    let id = dev_id.split(' ').collect::<Vec<&str>>()[0];
+    let device = first_device();
+    let state = device["state"].as_bool().unwrap_or_default();
 
-    reqwest::blocking::get(format!(
-        "{0}/device/{1}/state",
-        "http://127.0.0.1:8080",
-        id.trim_start_matches('"')
-    ))
-    .unwrap()
-    .text()
-    .unwrap();
-
-    let device_description = reqwest::blocking::get(format!(
-        "{0}/device/{1}",
+    let url = format!(
+        "{0}/api/v1/device/{1}",
         "http://127.0.0.1:8080",
-        id.trim_start_matches('"')
-    ))
-    .unwrap()
-    .text()
-    .unwrap();
+        device["id"].as_str().unwrap_or_default()
+    );
+    let change: serde_json::Value = reqwest::blocking::Client::new()
+        .put(format!("{url}/state"))
+        .json(&serde_json::json!({ "state": !state, "version": device["version"] }))
+        .send()
+        .unwrap()
+        .json()
+        .unwrap();
 
-    CString::new(device_description).unwrap().into_raw()
+    CString::new(change["device"].to_string())
+        .unwrap()
+        .into_raw()
 }
 
 #[cfg(test)]
@@ -81,30 +81,14 @@ impl SmartDevice {
     fn update(&mut self, message: SmartDeviceMessage) {
         match message {
             SmartDeviceMessage::Connect => {
-                let list_of_devices =
-                    reqwest::blocking::get(format!("{}/devices-list", &self.input_url))
+                let devices: Vec<Device> =
+                    reqwest::blocking::get(format!("{}/api/v1/devices?limit=1", &self.input_url))
                         .unwrap()
-                        .text()
+                        .json()
                         .unwrap();
-                if list_of_devices.contains("No devices") {
+                let Some(d) = devices.into_iter().next() else {
                     todo!()
-                }
-                let devices: Vec<&str> = serde_json::from_str(&list_of_devices).unwrap();
-                let device_id: &str = devices[0];
-                println!(
-                    "{}/device/{}",
-                    &self.input_url,
-                    device_id.trim_start_matches('"')
-                );
-                let device = reqwest::blocking::get(format!(
-                    "{0}/device/{1}",
-                    &self.input_url,
-                    device_id.trim_start_matches('"')
-                ))
-                .unwrap()
-                .text()
-                .unwrap();
-                let d: Device = serde_json::from_str(&device).unwrap();
+                };
                 self.device = d;
                 self.connected = true;
                 self.state = SmartDeviceState::Connected;
@@ -113,15 +97,18 @@ impl SmartDevice {
                 self.connected = connected;
             }
             SmartDeviceMessage::TurnLamp => {
-                reqwest::blocking::get(format!(
-                    "{0}/device/{1}/state",
-                    &self.input_url, &self.device.id
-                ))
-                .unwrap()
-                .text()
-                .unwrap();
+                reqwest::blocking::Client::new()
+                    .put(format!(
+                        "{0}/api/v1/device/{1}/state",
+                        &self.input_url, &self.device.id
+                    ))
+                    .json(&serde_json::json!({ "state": !self.device.state }))
+                    .send()
+                    .unwrap()
+                    .text()
+                    .unwrap();
                 let device = reqwest::blocking::get(format!(
-                    "{0}/device/{1}",
+                    "{0}/api/v1/device/{1}",
                     &self.input_url, &self.device.id
                 ))
                 .unwrap()
@@ -0,0 +1,2 @@
+UPDATE devices SET type = 'SmartSocket' WHERE type = 'socket';
+UPDATE devices SET type = 'SmartThermometer' WHERE type = 'thermometer';
@@ -0,0 +1,12 @@
+-- Map the free-form device types to the canonical `DeviceKind` names.
+UPDATE devices SET type = 'socket'
+  WHERE lower(type) LIKE '%socket%' OR lower(type) LIKE '%plug%' OR lower(type) LIKE '%outlet%';
+UPDATE devices SET type = 'thermometer' WHERE lower(type) LIKE '%thermometer%';
+UPDATE devices SET type = 'dimmer' WHERE lower(type) LIKE '%dimmer%';
+UPDATE devices SET type = 'lamp'
+  WHERE lower(type) LIKE '%lamp%' OR lower(type) LIKE '%light%' OR lower(type) LIKE '%bulb%';
+UPDATE devices SET type = 'switch' WHERE lower(type) LIKE '%switch%';
+
+-- Anything we could not recognise is kept as a generic read-only sensor.
+UPDATE devices SET type = 'sensor'
+  WHERE type NOT IN ('socket', 'thermometer', 'lamp', 'switch', 'dimmer', 'sensor');
@@ -0,0 +1,9 @@
+CREATE TABLE readings (
+  device VARCHAR NOT NULL,
+  metric VARCHAR NOT NULL,
+  value DOUBLE NOT NULL,
+  unit VARCHAR NOT NULL,
+  updated_at BIGINT NOT NULL,
+  PRIMARY KEY (device, metric),
+  FOREIGN KEY (device) REFERENCES devices(id) ON DELETE CASCADE
+);
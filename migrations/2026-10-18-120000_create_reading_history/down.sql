@@ -0,0 +1,2 @@
+DROP INDEX reading_history_series;
+DROP TABLE reading_history;
@@ -0,0 +1,11 @@
+CREATE TABLE reading_history (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  device VARCHAR NOT NULL,
+  metric VARCHAR NOT NULL,
+  value DOUBLE NOT NULL,
+  unit VARCHAR NOT NULL,
+  recorded_at BIGINT NOT NULL,
+  FOREIGN KEY (device) REFERENCES devices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX reading_history_series ON reading_history (device, metric, recorded_at);
@@ -0,0 +1 @@
+ALTER TABLE devices ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,7 @@
+DELETE FROM devices WHERE deleted_at IS NOT NULL;
+DELETE FROM rooms WHERE deleted_at IS NOT NULL;
+DELETE FROM houses WHERE deleted_at IS NOT NULL;
+
+ALTER TABLE devices DROP COLUMN deleted_at;
+ALTER TABLE rooms DROP COLUMN deleted_at;
+ALTER TABLE houses DROP COLUMN deleted_at;
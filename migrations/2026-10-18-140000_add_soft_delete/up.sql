@@ -0,0 +1,4 @@
+-- Deleted entities stay in the trash until they are restored or purged.
+ALTER TABLE houses ADD COLUMN deleted_at BIGINT;
+ALTER TABLE rooms ADD COLUMN deleted_at BIGINT;
+ALTER TABLE devices ADD COLUMN deleted_at BIGINT;
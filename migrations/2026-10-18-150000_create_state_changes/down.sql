@@ -0,0 +1,2 @@
+DROP INDEX state_changes_series;
+DROP TABLE state_changes;
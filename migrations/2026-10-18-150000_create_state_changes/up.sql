@@ -0,0 +1,13 @@
+CREATE TABLE state_changes (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  device VARCHAR NOT NULL,
+  state BOOLEAN NOT NULL,
+  changed_at BIGINT NOT NULL,
+  FOREIGN KEY (device) REFERENCES devices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX state_changes_series ON state_changes (device, changed_at);
+
+-- devices start their record in the state they are in now
+INSERT INTO state_changes (device, state, changed_at)
+SELECT id, state, CAST(strftime('%s', 'now') AS BIGINT) FROM devices;
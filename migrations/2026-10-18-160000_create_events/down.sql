@@ -0,0 +1,4 @@
+DROP INDEX events_at;
+DROP INDEX events_house;
+DROP INDEX events_entity;
+DROP TABLE events;
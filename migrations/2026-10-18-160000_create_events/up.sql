@@ -0,0 +1,16 @@
+-- append-only audit log; no foreign keys so that events outlive purged entities
+CREATE TABLE events (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  at BIGINT NOT NULL,
+  action VARCHAR NOT NULL,
+  entity VARCHAR NOT NULL,
+  entity_id VARCHAR NOT NULL,
+  house VARCHAR NOT NULL,
+  client VARCHAR NOT NULL,
+  old_value TEXT,
+  new_value TEXT
+);
+
+CREATE INDEX events_entity ON events (entity_id, id);
+CREATE INDEX events_house ON events (house, id);
+CREATE INDEX events_at ON events (at);
@@ -0,0 +1,186 @@
+use std::net::UdpSocket;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, thread};
+
+const USAGE: &str = "\
+Emulates a thermometer pushing readings to the server's UDP listener.
+
+Usage: thermometer --device UUID [--server ADDRESS] [--interval SECS]
+                   [--temperature CELSIUS] [--count N] [--json]
+
+  --device UUID            id of the thermometer on the server
+  --server ADDRESS         address of the UDP listener (default 127.0.0.1:7990)
+  --interval SECS          seconds between readings (default 5)
+  --temperature CELSIUS    temperature the readings wander around (default 21)
+  --count N                number of readings to send, 0 for no end (default 0)
+  --json                   send JSON datagrams instead of binary ones";
+
+#[derive(Debug)]
+struct Options {
+    device: [u8; 16],
+    server: String,
+    interval: f64,
+    temperature: f32,
+    count: u64,
+    json: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            device: [0; 16],
+            server: String::from("127.0.0.1:7990"),
+            interval: 5.0,
+            temperature: 21.0,
+            count: 0,
+            json: false,
+        };
+        let mut device = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" | "-h" => return Err(String::new()),
+                "--json" => {
+                    options.json = true;
+                    continue;
+                }
+                _ => {}
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value of {arg}"))?;
+            let invalid = || format!("invalid value of {arg}: {value}");
+            match arg.as_str() {
+                "--device" => device = Some(parse_uuid(&value).ok_or_else(invalid)?),
+                "--server" => options.server = value.clone(),
+                "--interval" => {
+                    options.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|secs: &f64| secs.is_finite() && *secs >= 0.0)
+                        .ok_or_else(invalid)?
+                }
+                "--temperature" => options.temperature = value.parse().map_err(|_| invalid())?,
+                "--count" => options.count = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown option {arg}")),
+            }
+        }
+
+        options.device = device.ok_or("missing --device")?;
+        Ok(options)
+    }
+}
+
+/// Bytes of a UUID written as 32 hex digits, with or without hyphens.
+fn parse_uuid(text: &str) -> Option<[u8; 16]> {
+    let digits: Vec<u8> = text.bytes().filter(|&b| b != b'-').collect();
+    if digits.len() != 32 {
+        return None;
+    }
+
+    let mut bytes = [0; 16];
+    for (byte, pair) in bytes.iter_mut().zip(digits.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+/// UUID bytes in their hyphenated text form.
+fn format_uuid(bytes: &[u8; 16]) -> String {
+    let mut text = String::with_capacity(36);
+    for (i, byte) in bytes.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            text.push('-');
+        }
+        text.push_str(&format!("{byte:02x}"));
+    }
+    text
+}
+
+/// Datagram of one reading: JSON, or the UUID bytes followed by the
+/// temperature as a big-endian `f32`.
+fn datagram(device: &[u8; 16], celsius: f32, json: bool) -> Vec<u8> {
+    if json {
+        return format!(
+            r#"{{"device": "{0}", "temperature": {celsius:.2}, "unit": "°C"}}"#,
+            format_uuid(device)
+        )
+        .into_bytes();
+    }
+
+    let mut datagram = device.to_vec();
+    datagram.extend_from_slice(&celsius.to_be_bytes());
+    datagram
+}
+
+/// Temperature wandering up to half a degree around `base`.
+fn temperature(base: f32) -> f32 {
+    let jitter = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_micros() % 1000)
+        .unwrap_or_default();
+
+    base - 0.5 + jitter as f32 / 1000.0
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{message}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(if message.is_empty() { 0 } else { 2 });
+        }
+    };
+
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("opening a UDP socket failed: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut sent = 0;
+    while options.count == 0 || sent < options.count {
+        if sent > 0 {
+            thread::sleep(Duration::from_secs_f64(options.interval));
+        }
+
+        let celsius = temperature(options.temperature);
+        let datagram = datagram(&options.device, celsius, options.json);
+        match socket.send_to(&datagram, &options.server) {
+            Ok(_) => println!("{0}: {celsius:.2} °C", options.server),
+            Err(e) => eprintln!("sending to {0} failed: {e}", options.server),
+        }
+        sent += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thermometer_datagrams() {
+        let text = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let device = parse_uuid(text).unwrap();
+        assert_eq!(format_uuid(&device), text);
+        assert_eq!(parse_uuid(&text.replace('-', "")), Some(device));
+        assert_eq!(parse_uuid("67e55044"), None);
+        assert_eq!(parse_uuid("zze55044-10b1-426f-9247-bb680e5fe0c8"), None);
+
+        let binary = datagram(&device, 21.5, false);
+        assert_eq!(binary.len(), 20);
+        assert_eq!(binary[..16], device);
+        assert_eq!(f32::from_be_bytes(binary[16..].try_into().unwrap()), 21.5);
+
+        let json = String::from_utf8(datagram(&device, 21.5, true)).unwrap();
+        assert_eq!(
+            json,
+            format!(r#"{{"device": "{text}", "temperature": 21.50, "unit": "°C"}}"#)
+        );
+    }
+}
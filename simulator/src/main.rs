@@ -0,0 +1,216 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, thread};
+
+const USAGE: &str = "\
+Emulates smart sockets speaking the line protocol of the server's `tcp` driver.
+
+Usage: simulator [--sockets N] [--port PORT] [--host HOST] [--power WATTS]
+
+  --sockets N      number of sockets, listening on consecutive ports (default 3)
+  --port PORT      port of the first socket (default 7890)
+  --host HOST      address to listen on (default 127.0.0.1)
+  --power WATTS    power drawn by a socket while on (default 1000)";
+
+/// Emulated smart socket.
+#[derive(Debug)]
+struct Socket {
+    name: String,
+    on: bool,
+    rated_power: f64,
+}
+
+impl Socket {
+    fn new(name: String, rated_power: f64) -> Self {
+        Self {
+            name,
+            on: false,
+            rated_power,
+        }
+    }
+
+    /// Reply to one command line.
+    fn handle(&mut self, command: &str) -> String {
+        match command.trim().to_ascii_lowercase().as_str() {
+            "on" => {
+                self.on = true;
+                String::from("ok")
+            }
+            "off" => {
+                self.on = false;
+                String::from("ok")
+            }
+            "status" => String::from(if self.on { "on" } else { "off" }),
+            "power" => format!("{0:.1}", self.power()),
+            other => format!("err unknown command: {other}"),
+        }
+    }
+
+    /// Power drawn, wandering a few percent around the rated power while on.
+    fn power(&self) -> f64 {
+        if !self.on {
+            return 0.0;
+        }
+        let jitter = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_micros() % 1000)
+            .unwrap_or_default();
+
+        self.rated_power * (0.95 + f64::from(jitter) / 10_000.0)
+    }
+}
+
+#[derive(Debug)]
+struct Options {
+    sockets: u16,
+    port: u16,
+    host: String,
+    power: f64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            sockets: 3,
+            port: 7890,
+            host: String::from("127.0.0.1"),
+            power: 1000.0,
+        }
+    }
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(String::new());
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value of {arg}"))?;
+            let invalid = || format!("invalid value of {arg}: {value}");
+            match arg.as_str() {
+                "--sockets" => options.sockets = value.parse().map_err(|_| invalid())?,
+                "--port" => options.port = value.parse().map_err(|_| invalid())?,
+                "--host" => options.host = value.clone(),
+                "--power" => options.power = value.parse().map_err(|_| invalid())?,
+                _ => return Err(format!("unknown option {arg}")),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Answers the commands of one connection until it is closed.
+fn session(stream: TcpStream, socket: &Mutex<Socket>) -> io::Result<()> {
+    let mut replies = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut socket = socket
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let reply = socket.handle(&line);
+        println!("{0}: {1} -> {reply}", socket.name, line.trim());
+        drop(socket);
+
+        writeln!(replies, "{reply}")?;
+    }
+
+    Ok(())
+}
+
+/// Starts serving a socket, returning the address it listens on.
+fn start(address: (&str, u16), socket: Socket) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let local = listener.local_addr()?;
+    let socket = Arc::new(Mutex::new(socket));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("accepting a connection on {local} failed: {e}");
+                    continue;
+                }
+            };
+            let socket = Arc::clone(&socket);
+            thread::spawn(move || {
+                if let Err(e) = session(stream, &socket) {
+                    eprintln!("connection to {local} failed: {e}");
+                }
+            });
+        }
+    });
+
+    Ok(local)
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{message}\n");
+            }
+            eprintln!("{USAGE}");
+            std::process::exit(if message.is_empty() { 0 } else { 2 });
+        }
+    };
+
+    for n in 0..options.sockets {
+        let Some(port) = options.port.checked_add(n) else {
+            eprintln!("not enough ports above {0}", options.port);
+            std::process::exit(1);
+        };
+        let socket = Socket::new(format!("socket-{0}", n + 1), options.power);
+        match start((&options.host, port), socket) {
+            Ok(address) => println!("socket-{0} listening on {address}", n + 1),
+            Err(e) => {
+                eprintln!("listening on {0}:{port} failed: {e}", options.host);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // the sockets are served by their own threads
+    loop {
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_protocol() {
+        let address = start(("127.0.0.1", 0), Socket::new(String::from("test"), 2000.0)).unwrap();
+        let stream = TcpStream::connect(address).unwrap();
+        let mut replies = BufReader::new(stream.try_clone().unwrap()).lines();
+        let mut send = |command: &str| {
+            writeln!(&stream, "{command}").unwrap();
+            replies.next().unwrap().unwrap()
+        };
+
+        assert_eq!(send("status"), "off");
+        assert_eq!(send("power"), "0.0");
+        assert_eq!(send("on"), "ok");
+        assert_eq!(send("STATUS"), "on");
+        let power: f64 = send("power").parse().unwrap();
+        assert!((1900.0..=2100.0).contains(&power));
+        assert_eq!(send("dim"), "err unknown command: dim");
+        assert_eq!(send("off"), "ok");
+        assert_eq!(send("status"), "off");
+    }
+}
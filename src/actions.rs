@@ -1,153 +1,874 @@
-use crate::models;
-use crate::report_generator::generate_report;
+use crate::drivers::Drivers;
+use crate::error::AppError;
+use crate::models::{self, EventAction, Item};
 use diesel::prelude::*;
+use serde::Serialize;
 use uuid::Uuid;
 
-type DbError = Box<dyn std::error::Error + Send + Sync>;
+/// How far in the future a reported timestamp may be, in seconds.
+const MAX_CLOCK_SKEW: i64 = 60;
+
+/// Current time as seconds since the Unix epoch.
+pub fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default()
+}
 
 /// Run query using Diesel to find device by uid and return it.
 pub fn find_device_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Option<models::Device>, DbError> {
+) -> Result<Option<models::Device>, AppError> {
     use crate::schema::devices::dsl::*;
 
     let device = devices
         .filter(id.eq(uid.to_string()))
+        .filter(deleted_at.is_null())
         .first::<models::Device>(conn)
         .optional()?;
 
     Ok(device)
 }
 
-/// Run query using Diesel to find device by uid and return it.
-pub fn get_devices_list(conn: &mut SqliteConnection) -> Result<Vec<models::Device>, DbError> {
+/// Run query using Diesel to list the devices of the given kinds which are not deleted.
+pub fn list_devices_of_kinds(
+    conn: &mut SqliteConnection,
+    kinds: &[models::DeviceKind],
+) -> Result<Vec<models::Device>, AppError> {
     use crate::schema::devices::dsl::*;
 
-    let devices_list = devices.load::<models::Device>(conn)?;
+    let found = devices
+        .filter(type_.eq_any(kinds))
+        .filter(deleted_at.is_null())
+        .order(id.asc())
+        .load::<models::Device>(conn)?;
+
+    Ok(found)
+}
+
+/// Rejects filters and sort keys which don't apply to the listed entity.
+fn reject_filters(
+    query: &models::ListQuery,
+    entity: &str,
+    filters: &[(&str, bool)],
+) -> Result<(), AppError> {
+    if let Some((filter, _)) = filters.iter().find(|(_, present)| *present) {
+        return Err(AppError::Validation(format!(
+            "{entity} can't be filtered by {filter}"
+        )));
+    }
 
-    Ok(devices_list)
+    let device_sort = matches!(
+        query.sort.map(|sort| sort.field),
+        Some(models::SortField::Type | models::SortField::State)
+    );
+    if device_sort && entity != "devices" {
+        return Err(AppError::Validation(format!(
+            "{entity} can only be sorted by id or name"
+        )));
+    }
+
+    Ok(())
 }
 
-pub fn get_rooms_list(conn: &mut SqliteConnection) -> Result<Vec<models::Room>, DbError> {
+/// Run query using Diesel to list one page of devices.
+pub fn get_devices_list(
+    conn: &mut SqliteConnection,
+    query: &models::ListQuery,
+) -> Result<models::Page<models::Device>, AppError> {
+    use crate::schema::devices::dsl::*;
+    use crate::schema::rooms::dsl as rms;
+
+    let mut select = devices.filter(deleted_at.is_null()).into_boxed();
+    if let Some(kind) = query.kind {
+        select = select.filter(type_.eq(kind));
+    }
+    if let Some(other_state) = query.state {
+        select = select.filter(state.eq(other_state));
+    }
+    if let Some(other_room) = &query.room {
+        select = select.filter(room.eq(other_room.clone()));
+    }
+    if let Some(other_house) = &query.house {
+        select = select.filter(
+            room.eq_any(
+                rms::rooms
+                    .filter(rms::house.eq(other_house.clone()))
+                    .filter(rms::deleted_at.is_null())
+                    .select(rms::id),
+            ),
+        );
+    }
+    if let Some(pattern) = query.name_pattern() {
+        select = select.filter(name.like(pattern).escape('\\'));
+    }
+
+    let sort = query.sort.unwrap_or_default();
+    select = match (sort.field, sort.descending) {
+        (models::SortField::Id, false) => select.order(id.asc()),
+        (models::SortField::Id, true) => select.order(id.desc()),
+        (models::SortField::Name, false) => select.order(name.asc()),
+        (models::SortField::Name, true) => select.order(name.desc()),
+        (models::SortField::Type, false) => select.order(type_.asc()),
+        (models::SortField::Type, true) => select.order(type_.desc()),
+        (models::SortField::State, false) => select.order(state.asc()),
+        (models::SortField::State, true) => select.order(state.desc()),
+    };
+
+    let items = select
+        .then_order_by(id.asc())
+        .limit(query.limit() + 1)
+        .offset(query.offset())
+        .load::<models::Device>(conn)?;
+
+    Ok(models::Page::new(items, query))
+}
+
+/// Run query using Diesel to list one page of rooms.
+pub fn get_rooms_list(
+    conn: &mut SqliteConnection,
+    query: &models::ListQuery,
+) -> Result<models::Page<models::Room>, AppError> {
     use crate::schema::rooms::dsl::*;
 
-    let rooms_list = rooms.load::<models::Room>(conn)?;
+    reject_filters(
+        query,
+        "rooms",
+        &[
+            ("type", query.kind.is_some()),
+            ("state", query.state.is_some()),
+            ("room", query.room.is_some()),
+        ],
+    )?;
+
+    let mut select = rooms.filter(deleted_at.is_null()).into_boxed();
+    if let Some(other_house) = &query.house {
+        select = select.filter(house.eq(other_house.clone()));
+    }
+    if let Some(pattern) = query.name_pattern() {
+        select = select.filter(name.like(pattern).escape('\\'));
+    }
+
+    let sort = query.sort.unwrap_or_default();
+    select = match (sort.field, sort.descending) {
+        (models::SortField::Id, false) => select.order(id.asc()),
+        (models::SortField::Id, true) => select.order(id.desc()),
+        (_, false) => select.order(name.asc()),
+        (_, true) => select.order(name.desc()),
+    };
+
+    let items = select
+        .then_order_by(id.asc())
+        .limit(query.limit() + 1)
+        .offset(query.offset())
+        .load::<models::Room>(conn)?;
+
+    Ok(models::Page::new(items, query))
+}
+
+/// Run query using Diesel to list one page of the rooms of a house.
+pub fn list_room_by_id(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    query: &models::ListQuery,
+) -> Result<models::Page<models::Room>, AppError> {
+    reject_filters(query, "rooms", &[("house", query.house.is_some())])?;
+
+    let query = models::ListQuery {
+        house: Some(uid.to_string()),
+        ..query.clone()
+    };
+    get_rooms_list(conn, &query)
+}
+
+/// UIDs of all live houses, ordered by name.
+pub fn live_house_ids(conn: &mut SqliteConnection) -> Result<Vec<Uuid>, AppError> {
+    use crate::schema::houses::dsl::*;
+
+    let ids = houses
+        .filter(deleted_at.is_null())
+        .order((name, id))
+        .select(id)
+        .load::<String>(conn)?;
+
+    Ok(ids
+        .iter()
+        .filter_map(|other_id| Uuid::parse_str(other_id).ok())
+        .collect())
+}
+
+/// Run query using Diesel to list one page of houses.
+pub fn list_houses(
+    conn: &mut SqliteConnection,
+    query: &models::ListQuery,
+) -> Result<models::Page<models::House>, AppError> {
+    use crate::schema::houses::dsl::*;
+
+    reject_filters(
+        query,
+        "houses",
+        &[
+            ("type", query.kind.is_some()),
+            ("state", query.state.is_some()),
+            ("room", query.room.is_some()),
+            ("house", query.house.is_some()),
+        ],
+    )?;
+
+    let mut select = houses.filter(deleted_at.is_null()).into_boxed();
+    if let Some(pattern) = query.name_pattern() {
+        select = select.filter(name.like(pattern).escape('\\'));
+    }
+
+    let sort = query.sort.unwrap_or_default();
+    select = match (sort.field, sort.descending) {
+        (models::SortField::Id, false) => select.order(id.asc()),
+        (models::SortField::Id, true) => select.order(id.desc()),
+        (_, false) => select.order(name.asc()),
+        (_, true) => select.order(name.desc()),
+    };
+
+    let items = select
+        .then_order_by(id.asc())
+        .limit(query.limit() + 1)
+        .offset(query.offset())
+        .load::<models::House>(conn)?;
 
-    Ok(rooms_list)
+    Ok(models::Page::new(items, query))
 }
 
-/// Run query using Diesel to remove device by uid and return it.
+/// Run query using Diesel to list one page of the devices in a room.
+pub fn list_device_in_room(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    query: &models::ListQuery,
+) -> Result<models::Page<models::Device>, AppError> {
+    reject_filters(
+        query,
+        "devices",
+        &[
+            ("room", query.room.is_some()),
+            ("house", query.house.is_some()),
+        ],
+    )?;
+
+    let query = models::ListQuery {
+        room: Some(uid.to_string()),
+        ..query.clone()
+    };
+    get_devices_list(conn, &query)
+}
+
+/// Groups devices under their rooms.
+///
+/// Only devices deleted at `deleted` are included; `None` selects the live ones.
+fn room_trees(
+    conn: &mut SqliteConnection,
+    rooms: Vec<models::Room>,
+    deleted: Option<i64>,
+) -> Result<Vec<models::RoomTree>, AppError> {
+    use crate::schema::devices::dsl::*;
+
+    let room_ids: Vec<&str> = rooms
+        .iter()
+        .map(|other_room| other_room.id.as_str())
+        .collect();
+    let query = devices.filter(room.eq_any(&room_ids)).into_boxed();
+    let query = match deleted {
+        Some(timestamp) => query.filter(deleted_at.eq(timestamp)),
+        None => query.filter(deleted_at.is_null()),
+    };
+    let mut all_devices = query.order(name).load::<models::Device>(conn)?;
+
+    Ok(rooms
+        .into_iter()
+        .map(|other_room| {
+            let (in_room, rest) = all_devices
+                .drain(..)
+                .partition(|device| device.room == other_room.id);
+            all_devices = rest;
+            models::RoomTree {
+                room: other_room,
+                devices: in_room,
+            }
+        })
+        .collect())
+}
+
+/// Counts the rows removed together with the given rooms and devices once
+/// they are purged from the trash.
+fn cascade_counts<'a>(
+    conn: &mut SqliteConnection,
+    room_count: usize,
+    device_ids: impl Iterator<Item = &'a str>,
+) -> Result<models::CascadeCounts, AppError> {
+    use crate::schema::{reading_history as rh, readings as rd};
+
+    let device_ids: Vec<&str> = device_ids.collect();
+    let readings = rd::table
+        .filter(rd::device.eq_any(&device_ids))
+        .count()
+        .get_result::<i64>(conn)?;
+    let history = rh::table
+        .filter(rh::device.eq_any(&device_ids))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    Ok(models::CascadeCounts {
+        rooms: room_count as i64,
+        devices: device_ids.len() as i64,
+        readings,
+        history,
+    })
+}
+
+/// Run query using Diesel to move device by uid to the trash and return it.
+///
+/// Its readings and history stay until the device is purged. A dry run only
+/// reports what would be removed.
 pub fn remove_device_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Option<models::Device>, DbError> {
+    options: &models::DeleteOptions,
+    actor: &models::Actor,
+) -> Result<Option<models::Removal<models::Device>>, AppError> {
     use crate::schema::devices::dsl::*;
 
-    let old_count = devices.count().first::<i64>(conn);
-    let device = devices
-        .filter(id.eq(uid.to_string()))
-        .first::<models::Device>(conn)
-        .optional()?;
+    conn.immediate_transaction(|conn| {
+        let Some(device) = devices
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_null())
+            .first::<models::Device>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let counts = cascade_counts(conn, 0, std::iter::once(device.id.as_str()))?;
 
-    diesel::delete(devices.filter(id.eq(uid.to_string()))).execute(conn)?;
+        let device = if options.dry_run {
+            device
+        } else {
+            let house_id = house_of_room(conn, &device.room)?;
+            record_event(
+                conn,
+                actor,
+                EventAction::Delete,
+                &house_id,
+                Some(&device),
+                None,
+            )?;
+            diesel::update(devices.find(&device.id))
+                .set(deleted_at.eq(unix_now()))
+                .get_result::<models::Device>(conn)?
+        };
 
-    if old_count.map(|count| count - 1) == devices.count().first(conn) {
-        Ok(device)
-    } else {
-        Err(DbError::from("Error finding device for remove"))
-    }
+        Ok(Some(models::Removal {
+            tree: device,
+            counts,
+            dry_run: options.dry_run,
+        }))
+    })
 }
 
-/// Run query using Diesel to remove room by uid and return it.
+/// Run query using Diesel to move room by uid to the trash and return it with
+/// its devices.
+///
+/// Devices of the room go to the trash with it, unless cascading is off, in
+/// which case a room that still has devices is left untouched. A dry run only
+/// reports what would be removed.
 pub fn remove_room_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Option<models::Room>, DbError> {
+    options: &models::DeleteOptions,
+    actor: &models::Actor,
+) -> Result<Option<models::Removal<models::RoomTree>>, AppError> {
+    use crate::schema::devices::dsl as dvs;
     use crate::schema::rooms::dsl::*;
 
-    let old_count = rooms.count().first::<i64>(conn);
-    let room = rooms
-        .filter(id.eq(uid.to_string()))
-        .first::<models::Room>(conn)
-        .optional()?;
+    conn.immediate_transaction(|conn| {
+        let Some(other_room) = rooms
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_null())
+            .first::<models::Room>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let mut tree = room_trees(conn, vec![other_room], None)?.remove(0);
 
-    diesel::delete(rooms.filter(id.eq(uid.to_string()))).execute(conn)?;
+        if !options.cascade && !tree.devices.is_empty() {
+            return Err(AppError::Conflict(format!("Room {uid} still has devices")));
+        }
+        let counts = cascade_counts(
+            conn,
+            0,
+            tree.devices.iter().map(|device| device.id.as_str()),
+        )?;
 
-    if old_count.map(|count| count - 1) == rooms.count().first(conn) {
-        Ok(room)
-    } else {
-        Err(DbError::from("Error finding room for remove"))
-    }
+        if !options.dry_run {
+            let house_id = &tree.room.house;
+            record_event(
+                conn,
+                actor,
+                EventAction::Delete,
+                house_id,
+                Some(&tree.room),
+                None,
+            )?;
+            for device in &tree.devices {
+                record_event(
+                    conn,
+                    actor,
+                    EventAction::Delete,
+                    house_id,
+                    Some(device),
+                    None,
+                )?;
+            }
+
+            // children share the timestamp of their parent so that they are restored with it
+            let now = unix_now();
+            tree.devices = diesel::update(
+                dvs::devices
+                    .filter(dvs::room.eq(&tree.room.id))
+                    .filter(dvs::deleted_at.is_null()),
+            )
+            .set(dvs::deleted_at.eq(now))
+            .get_results::<models::Device>(conn)?;
+            tree.room = diesel::update(rooms.find(&tree.room.id))
+                .set(deleted_at.eq(now))
+                .get_result::<models::Room>(conn)?;
+        }
+
+        Ok(Some(models::Removal {
+            tree,
+            counts,
+            dry_run: options.dry_run,
+        }))
+    })
 }
 
+/// Run query using Diesel to move house by uid to the trash and return it with
+/// its rooms and devices.
+///
+/// Rooms and devices of the house go to the trash with it, unless cascading is
+/// off, in which case a house that still has rooms is left untouched. A dry run
+/// only reports what would be removed.
 pub fn remove_house_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Option<models::House>, DbError> {
+    options: &models::DeleteOptions,
+    actor: &models::Actor,
+) -> Result<Option<models::Removal<models::HouseTree>>, AppError> {
+    use crate::schema::devices::dsl as dvs;
     use crate::schema::houses::dsl::*;
+    use crate::schema::rooms::dsl as rms;
 
-    let old_count = houses.count().first::<i64>(conn);
-    let other_house = houses
-        .filter(id.eq(uid.to_string()))
-        .first::<models::House>(conn)
-        .optional()?;
+    conn.immediate_transaction(|conn| {
+        let Some(other_house) = houses
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_null())
+            .first::<models::House>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let house_rooms = rms::rooms
+            .filter(rms::house.eq(&other_house.id))
+            .filter(rms::deleted_at.is_null())
+            .order(rms::name)
+            .load::<models::Room>(conn)?;
 
-    diesel::delete(houses.filter(id.eq(uid.to_string()))).execute(conn)?;
+        if !options.cascade && !house_rooms.is_empty() {
+            return Err(AppError::Conflict(format!("House {uid} still has rooms")));
+        }
+        let mut tree = models::HouseTree {
+            house: other_house,
+            rooms: room_trees(conn, house_rooms, None)?,
+        };
+        let device_ids: Vec<String> = tree
+            .rooms
+            .iter()
+            .flat_map(|room_tree| room_tree.devices.iter())
+            .map(|device| device.id.clone())
+            .collect();
+        let counts = cascade_counts(
+            conn,
+            tree.rooms.len(),
+            device_ids.iter().map(String::as_str),
+        )?;
 
-    if old_count.map(|count| count - 1) == houses.count().first(conn) {
-        Ok(other_house)
-    } else {
-        Err(DbError::from("Error finding house for remove"))
-    }
+        if !options.dry_run {
+            let house_id = &tree.house.id;
+            record_event(
+                conn,
+                actor,
+                EventAction::Delete,
+                house_id,
+                Some(&tree.house),
+                None,
+            )?;
+            for room_tree in &tree.rooms {
+                record_event(
+                    conn,
+                    actor,
+                    EventAction::Delete,
+                    house_id,
+                    Some(&room_tree.room),
+                    None,
+                )?;
+                for device in &room_tree.devices {
+                    record_event(
+                        conn,
+                        actor,
+                        EventAction::Delete,
+                        house_id,
+                        Some(device),
+                        None,
+                    )?;
+                }
+            }
+
+            // children share the timestamp of their parent so that they are restored with it
+            let now = unix_now();
+            let room_ids: Vec<&str> = tree
+                .rooms
+                .iter()
+                .map(|room_tree| room_tree.room.id.as_str())
+                .collect();
+            diesel::update(dvs::devices.filter(dvs::id.eq_any(&device_ids)))
+                .set(dvs::deleted_at.eq(now))
+                .execute(conn)?;
+            diesel::update(rms::rooms.filter(rms::id.eq_any(&room_ids)))
+                .set(rms::deleted_at.eq(now))
+                .execute(conn)?;
+            tree.house = diesel::update(houses.find(&tree.house.id))
+                .set(deleted_at.eq(now))
+                .get_result::<models::House>(conn)?;
+            for room_tree in &mut tree.rooms {
+                room_tree.room.deleted_at = Some(now);
+                for device in &mut room_tree.devices {
+                    device.deleted_at = Some(now);
+                }
+            }
+        }
+
+        Ok(Some(models::Removal {
+            tree,
+            counts,
+            dry_run: options.dry_run,
+        }))
+    })
 }
 
-/// Run query using Diesel to list rooms by uid of house and return it.
-pub fn list_room_by_id(
+/// Run query using Diesel to list the contents of the trash, latest deletions first.
+pub fn list_trash(conn: &mut SqliteConnection) -> Result<models::Trash, AppError> {
+    use crate::schema::{devices as dvs, houses as hs, rooms as rms};
+
+    let houses = hs::table
+        .filter(hs::deleted_at.is_not_null())
+        .order(hs::deleted_at.desc())
+        .load::<models::House>(conn)?;
+    let rooms = rms::table
+        .filter(rms::deleted_at.is_not_null())
+        .order(rms::deleted_at.desc())
+        .load::<models::Room>(conn)?;
+    let devices = dvs::table
+        .filter(dvs::deleted_at.is_not_null())
+        .order(dvs::deleted_at.desc())
+        .load::<models::Device>(conn)?;
+
+    Ok(models::Trash {
+        houses,
+        rooms,
+        devices,
+    })
+}
+
+/// Run query using Diesel to take device by uid out of the trash and return it.
+///
+/// Fails when its room is in the trash too or already holds a device with the
+/// same name.
+pub fn restore_device_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Vec<models::Room>, DbError> {
-    use crate::schema::rooms as rms;
+    actor: &models::Actor,
+) -> Result<Option<models::Device>, AppError> {
+    use crate::schema::devices::dsl::*;
+    use crate::schema::rooms::dsl as rms;
 
-    let rooms: Vec<models::Room> = rms::dsl::rooms
-        .filter(rms::dsl::house.eq(uid.to_string()))
-        .load::<models::Room>(conn)?;
+    conn.immediate_transaction(|conn| {
+        let Some(device) = devices
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_not_null())
+            .first::<models::Device>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let room_exists = diesel::select(diesel::dsl::exists(
+            rms::rooms
+                .filter(rms::id.eq(&device.room))
+                .filter(rms::deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if !room_exists {
+            return Err(AppError::ForeignKey(format!(
+                "Room {0} is deleted; restore it first",
+                device.room
+            )));
+        }
+
+        let name_taken = diesel::select(diesel::dsl::exists(
+            devices
+                .filter(room.eq(&device.room))
+                .filter(name.eq(&device.name))
+                .filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "Room {0} already has a device named {1}",
+                device.room, device.name
+            )));
+        }
 
-    Ok(rooms)
+        let device = diesel::update(devices.find(&device.id))
+            .set(deleted_at.eq(None::<i64>))
+            .get_result::<models::Device>(conn)?;
+        let house_id = house_of_room(conn, &device.room)?;
+        record_event(
+            conn,
+            actor,
+            EventAction::Restore,
+            &house_id,
+            None,
+            Some(&device),
+        )?;
+
+        Ok(Some(device))
+    })
 }
 
-/// Run query using Diesel to list rooms by uid of house and return it.
-pub fn list_houses(conn: &mut SqliteConnection) -> Result<Vec<models::House>, DbError> {
-    use crate::schema::houses as hs;
+/// Run query using Diesel to take room by uid out of the trash and return it
+/// with the devices deleted together with it.
+///
+/// Fails when its house is in the trash too or already has a room with the
+/// same name.
+pub fn restore_room_by_id(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    actor: &models::Actor,
+) -> Result<Option<models::RoomTree>, AppError> {
+    use crate::schema::devices::dsl as dvs;
+    use crate::schema::houses::dsl as hs;
+    use crate::schema::rooms::dsl::*;
+
+    conn.immediate_transaction(|conn| {
+        let Some(other_room) = rooms
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_not_null())
+            .first::<models::Room>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let house_exists = diesel::select(diesel::dsl::exists(
+            hs::houses
+                .filter(hs::id.eq(&other_room.house))
+                .filter(hs::deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if !house_exists {
+            return Err(AppError::ForeignKey(format!(
+                "House {0} is deleted; restore it first",
+                other_room.house
+            )));
+        }
 
-    let houses: Vec<models::House> = hs::dsl::houses.load::<models::House>(conn)?;
+        let name_taken = diesel::select(diesel::dsl::exists(
+            rooms
+                .filter(house.eq(&other_room.house))
+                .filter(name.eq(&other_room.name))
+                .filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "House {0} already has a room named {1}",
+                other_room.house, other_room.name
+            )));
+        }
 
-    Ok(houses)
+        let deleted = other_room.deleted_at;
+        let mut tree = room_trees(conn, vec![other_room], deleted)?.remove(0);
+        diesel::update(
+            dvs::devices
+                .filter(dvs::room.eq(&tree.room.id))
+                .filter(dvs::deleted_at.eq(deleted)),
+        )
+        .set(dvs::deleted_at.eq(None::<i64>))
+        .execute(conn)?;
+        tree.room = diesel::update(rooms.find(&tree.room.id))
+            .set(deleted_at.eq(None::<i64>))
+            .get_result::<models::Room>(conn)?;
+        let house_id = &tree.room.house;
+        record_event(
+            conn,
+            actor,
+            EventAction::Restore,
+            house_id,
+            None,
+            Some(&tree.room),
+        )?;
+        for device in &mut tree.devices {
+            device.deleted_at = None;
+            record_event(
+                conn,
+                actor,
+                EventAction::Restore,
+                house_id,
+                None,
+                Some(&*device),
+            )?;
+        }
+
+        Ok(Some(tree))
+    })
 }
 
-/// Run query using Diesel to list devices by uid in room and return it.
-pub fn list_device_in_room(
+/// Run query using Diesel to take house by uid out of the trash and return it
+/// with the rooms and devices deleted together with it.
+///
+/// Fails when another house already has the same name.
+pub fn restore_house_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Vec<models::Device>, DbError> {
-    use crate::schema::devices as dvs;
+    actor: &models::Actor,
+) -> Result<Option<models::HouseTree>, AppError> {
+    use crate::schema::devices::dsl as dvs;
+    use crate::schema::houses::dsl::*;
+    use crate::schema::rooms::dsl as rms;
 
-    let devices: Vec<models::Device> = dvs::dsl::devices
-        .filter(dvs::dsl::room.eq(uid.to_string()))
-        .load::<models::Device>(conn)?;
+    conn.immediate_transaction(|conn| {
+        let Some(other_house) = houses
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_not_null())
+            .first::<models::House>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let name_taken = diesel::select(diesel::dsl::exists(
+            houses
+                .filter(name.eq(&other_house.name))
+                .filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "A house named {0} already exists",
+                other_house.name
+            )));
+        }
 
-    Ok(devices)
+        let deleted = other_house.deleted_at;
+        let house_rooms = rms::rooms
+            .filter(rms::house.eq(&other_house.id))
+            .filter(rms::deleted_at.eq(deleted))
+            .order(rms::name)
+            .load::<models::Room>(conn)?;
+        let mut tree = models::HouseTree {
+            house: other_house,
+            rooms: room_trees(conn, house_rooms, deleted)?,
+        };
+
+        let room_ids: Vec<&str> = tree
+            .rooms
+            .iter()
+            .map(|room_tree| room_tree.room.id.as_str())
+            .collect();
+        diesel::update(
+            dvs::devices
+                .filter(dvs::room.eq_any(&room_ids))
+                .filter(dvs::deleted_at.eq(deleted)),
+        )
+        .set(dvs::deleted_at.eq(None::<i64>))
+        .execute(conn)?;
+        diesel::update(rms::rooms.filter(rms::id.eq_any(&room_ids)))
+            .set(rms::deleted_at.eq(None::<i64>))
+            .execute(conn)?;
+        tree.house = diesel::update(houses.find(&tree.house.id))
+            .set(deleted_at.eq(None::<i64>))
+            .get_result::<models::House>(conn)?;
+        let house_id = &tree.house.id;
+        record_event(
+            conn,
+            actor,
+            EventAction::Restore,
+            house_id,
+            None,
+            Some(&tree.house),
+        )?;
+        for room_tree in &mut tree.rooms {
+            room_tree.room.deleted_at = None;
+            record_event(
+                conn,
+                actor,
+                EventAction::Restore,
+                house_id,
+                None,
+                Some(&room_tree.room),
+            )?;
+            for device in &mut room_tree.devices {
+                device.deleted_at = None;
+                record_event(
+                    conn,
+                    actor,
+                    EventAction::Restore,
+                    house_id,
+                    None,
+                    Some(&*device),
+                )?;
+            }
+        }
+
+        Ok(Some(tree))
+    })
+}
+
+/// Run query using Diesel to remove entities which have been in the trash
+/// since before `cutoff` and return how many were removed.
+///
+/// Children, readings and history go with them through the foreign keys.
+pub fn purge_trash(conn: &mut SqliteConnection, cutoff: i64) -> Result<usize, AppError> {
+    use crate::schema::{devices as dvs, houses as hs, rooms as rms};
+
+    conn.transaction(|conn| {
+        let mut removed =
+            diesel::delete(hs::table.filter(hs::deleted_at.lt(cutoff))).execute(conn)?;
+        removed += diesel::delete(rms::table.filter(rms::deleted_at.lt(cutoff))).execute(conn)?;
+        removed += diesel::delete(dvs::table.filter(dvs::deleted_at.lt(cutoff))).execute(conn)?;
+
+        Ok(removed)
+    })
 }
 
 /// Run query using Diesel to find room by uid and return it.
 pub fn find_room_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Option<models::Room>, DbError> {
+) -> Result<Option<models::Room>, AppError> {
     use crate::schema::rooms::dsl::*;
 
     let room = rooms
         .filter(id.eq(uid.to_string()))
+        .filter(deleted_at.is_null())
         .first::<models::Room>(conn)
         .optional()?;
 
@@ -158,107 +879,839 @@ pub fn find_room_by_id(
 pub fn find_house_by_id(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Option<models::House>, DbError> {
+) -> Result<Option<models::House>, AppError> {
     use crate::schema::houses::dsl::*;
 
     let other_house = houses
         .filter(id.eq(uid.to_string()))
+        .filter(deleted_at.is_null())
         .first::<models::House>(conn)
         .optional()?;
 
     Ok(other_house)
 }
 
-/// Run query using Diesel to find room by uid and return it.
-pub fn get_house_report(conn: &mut SqliteConnection, uid: Uuid) -> Result<String, DbError> {
+/// Loads a live house with its rooms and their devices in one joined query.
+pub fn get_house_tree(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+) -> Result<Option<models::HouseTree>, AppError> {
     use crate::schema::devices::dsl as dvs;
     use crate::schema::houses::dsl as hs;
     use crate::schema::rooms::dsl as rms;
 
-    let other_house = hs::houses
+    // deleted children are filtered in the join conditions so that a house
+    // without live rooms, or a room without live devices, is still returned
+    let rows = hs::houses
+        .left_join(rms::rooms.on(rms::house.eq(hs::id).and(rms::deleted_at.is_null())))
+        .left_join(dvs::devices.on(dvs::room.eq(rms::id).and(dvs::deleted_at.is_null())))
         .filter(hs::id.eq(uid.to_string()))
-        .first::<models::House>(conn)?;
-    let mut report = String::from("");
-    report.push_str(&format!(
-        "В доме {0} установлены следующие приборы: \n",
-        other_house.name
-    ));
-    let rooms: Vec<_> = rms::rooms
-        .filter(rms::house.eq(other_house.id))
-        .select(rms::id)
-        .load::<String>(conn)?;
-    for room in rooms {
-        let devices: Vec<models::Device> = dvs::devices
-            .filter(dvs::room.eq(room.to_string()))
-            .load::<models::Device>(conn)?;
-        report.push_str(&generate_report(devices).unwrap());
+        .filter(hs::deleted_at.is_null())
+        .order((rms::name, rms::id, dvs::name, dvs::id))
+        .load::<(models::House, Option<models::Room>, Option<models::Device>)>(conn)?;
+
+    let mut tree: Option<models::HouseTree> = None;
+    for (other_house, other_room, device) in rows {
+        let tree = tree.get_or_insert_with(|| models::HouseTree {
+            house: other_house,
+            rooms: Vec::new(),
+        });
+        let Some(other_room) = other_room else {
+            continue;
+        };
+        if tree.rooms.last().map(|last| &last.room.id) != Some(&other_room.id) {
+            tree.rooms.push(models::RoomTree {
+                room: other_room,
+                devices: Vec::new(),
+            });
+        }
+        if let (Some(last), Some(device)) = (tree.rooms.last_mut(), device) {
+            last.devices.push(device);
+        }
     }
 
-    Ok(report)
+    Ok(tree)
+}
+
+/// Collects the report on a live house: its rooms, devices and their latest readings.
+pub fn get_house_report(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+) -> Result<Option<models::HouseReport>, AppError> {
+    use crate::schema::readings::dsl::*;
+
+    let Some(tree) = get_house_tree(conn, uid)? else {
+        return Ok(None);
+    };
+
+    let device_ids: Vec<&str> = tree
+        .rooms
+        .iter()
+        .flat_map(|room_tree| &room_tree.devices)
+        .map(|other_device| other_device.id.as_str())
+        .collect();
+    let mut all_readings = readings
+        .filter(device.eq_any(&device_ids))
+        .order((device, metric))
+        .load::<models::Reading>(conn)?;
+
+    let rooms = tree
+        .rooms
+        .into_iter()
+        .map(|room_tree| models::RoomReport {
+            room: room_tree.room,
+            devices: room_tree
+                .devices
+                .into_iter()
+                .map(|other_device| {
+                    let (own, rest) = all_readings
+                        .drain(..)
+                        .partition(|reading| reading.device == other_device.id);
+                    all_readings = rest;
+                    models::DeviceReport {
+                        device: other_device,
+                        readings: own,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(Some(models::HouseReport {
+        house: tree.house,
+        rooms,
+        generated_at: unix_now(),
+    }))
+}
+
+/// Samples of one metric of a device within `[from, to)` in W or kWh, led by the last sample before `from` if there is one.
+fn metric_series(
+    conn: &mut SqliteConnection,
+    device_id: &str,
+    series_metric: models::Metric,
+    from: i64,
+    to: i64,
+) -> Result<Vec<(i64, f64)>, AppError> {
+    use crate::schema::reading_history::dsl::*;
+
+    let samples = reading_history
+        .filter(device.eq(device_id))
+        .filter(metric.eq(series_metric));
+    let before = samples
+        .filter(recorded_at.lt(from))
+        .order((recorded_at.desc(), id.desc()))
+        .select((recorded_at, value, unit))
+        .first::<(i64, f64, String)>(conn)
+        .optional()?;
+    let within = samples
+        .filter(recorded_at.ge(from))
+        .filter(recorded_at.lt(to))
+        .order((recorded_at.asc(), id.asc()))
+        .select((recorded_at, value, unit))
+        .load::<(i64, f64, String)>(conn)?;
+
+    // kW and Wh are stored as reported; bring them to W and kWh
+    Ok(before
+        .into_iter()
+        .chain(within)
+        .map(|(at, sample, sample_unit)| match sample_unit.as_str() {
+            "kW" => (at, sample * 1000.0),
+            "Wh" => (at, sample / 1000.0),
+            _ => (at, sample),
+        })
+        .collect())
+}
+
+/// Switching of a device within `[from, to)`, led by the last one before `from`.
+fn state_series(
+    conn: &mut SqliteConnection,
+    device_id: &str,
+    from: i64,
+    to: i64,
+) -> Result<Vec<(i64, bool)>, AppError> {
+    use crate::schema::state_changes::dsl::*;
+
+    let changes = state_changes.filter(device.eq(device_id));
+    let before = changes
+        .filter(changed_at.lt(from))
+        .order((changed_at.desc(), id.desc()))
+        .select((changed_at, state))
+        .first::<(i64, bool)>(conn)
+        .optional()?;
+    let within = changes
+        .filter(changed_at.ge(from))
+        .filter(changed_at.lt(to))
+        .order((changed_at.asc(), id.asc()))
+        .select((changed_at, state))
+        .load::<(i64, bool)>(conn)?;
+
+    Ok(before.into_iter().chain(within).collect())
+}
+
+/// Part of `[from, to)` the sample at `index` of a series holds for, until the next one.
+fn held_span<T>(series: &[(i64, T)], index: usize, from: i64, to: i64) -> (i64, i64) {
+    let until = series.get(index + 1).map_or(to, |next| next.0);
+    (series[index].0.max(from), until.min(to))
+}
+
+/// Energy used within `[from, to)` out of power and meter samples and switching.
+///
+/// The energy comes from the meter when it was read at least twice, counting
+/// from its reading interpolated at `from`; otherwise every power sample is
+/// held until the next one and only counts while the device is on. A device
+/// is off until its first recorded switch.
+pub fn energy_usage(
+    power: &[(i64, f64)],
+    meter: &[(i64, f64)],
+    switching: &[(i64, bool)],
+    from: i64,
+    to: i64,
+) -> models::EnergyUsage {
+    let on_spans: Vec<(i64, i64)> = (0..switching.len())
+        .filter(|&index| switching[index].1)
+        .map(|index| held_span(switching, index, from, to))
+        .filter(|(start, end)| start < end)
+        .collect();
+    let power_spans: Vec<((i64, i64), f64)> = (0..power.len())
+        .map(|index| (held_span(power, index, from, to), power[index].1))
+        .filter(|((start, end), _)| start < end)
+        .collect();
+
+    let split = meter.partition_point(|(at, _)| *at < from);
+    let (before, within) = meter.split_at(split);
+    // the meter reading at `from`, unless it was reset in between
+    let at_from = match (before.last(), within.first()) {
+        (Some(&(before_at, start)), Some(&(next_at, next))) if next >= start => Some((
+            from,
+            start + (next - start) * (from - before_at) as f64 / (next_at - before_at) as f64,
+        )),
+        _ => None,
+    };
+    let meter: Vec<(i64, f64)> = at_from.into_iter().chain(within.iter().copied()).collect();
+
+    let energy_kwh = if meter.len() >= 2 {
+        // a meter going back was reset, its new reading is all new energy
+        meter
+            .windows(2)
+            .map(|pair| match pair[1].1 - pair[0].1 {
+                delta if delta >= 0.0 => delta,
+                _ => pair[1].1,
+            })
+            .sum()
+    } else {
+        let watt_seconds: f64 = power_spans
+            .iter()
+            .map(|&((start, end), watts)| {
+                let on_secs: i64 = on_spans
+                    .iter()
+                    .map(|&(on, off)| (off.min(end) - on.max(start)).max(0))
+                    .sum();
+                watts * on_secs as f64
+            })
+            .sum();
+        watt_seconds / 3_600_000.0
+    };
+
+    // the sample before the window counts as long as it holds into it
+    let peak_w = power_spans
+        .iter()
+        .map(|(_, watts)| *watts)
+        .fold(0.0, f64::max);
+
+    let on_time_secs = on_spans.iter().map(|(on, off)| off - on).sum();
+
+    models::EnergyUsage {
+        energy_kwh,
+        peak_w,
+        on_time_secs,
+    }
+}
+
+/// Collects the energy report of the power metering devices of a live house
+/// within `[from, to)`; a window reaching into the future ends now, and the
+/// report states the period it covers.
+pub fn get_house_energy(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    from: i64,
+    to: i64,
+) -> Result<Option<models::EnergyReport>, AppError> {
+    let Some(tree) = get_house_tree(conn, uid)? else {
+        return Ok(None);
+    };
+
+    let until = to.min(unix_now()).max(from);
+    let mut house_usage = models::EnergyUsage::default();
+    let mut rooms = Vec::with_capacity(tree.rooms.len());
+    for room_tree in tree.rooms {
+        let mut room_usage = models::EnergyUsage::default();
+        let mut sockets = Vec::new();
+        for device in room_tree.devices {
+            if !device
+                .type_
+                .capabilities()
+                .contains(&models::Capability::PowerMetering)
+            {
+                continue;
+            }
+
+            let power = metric_series(conn, &device.id, models::Metric::Power, from, until)?;
+            let meter = metric_series(conn, &device.id, models::Metric::Energy, from, until)?;
+            let switching = state_series(conn, &device.id, from, until)?;
+            let usage = energy_usage(&power, &meter, &switching, from, until);
+
+            room_usage.add(&usage);
+            sockets.push(models::DeviceEnergy {
+                id: device.id,
+                name: device.name,
+                usage,
+            });
+        }
+
+        house_usage.add(&room_usage);
+        rooms.push(models::RoomEnergy {
+            id: room_tree.room.id,
+            name: room_tree.room.name,
+            usage: room_usage,
+            devices: sockets,
+        });
+    }
+
+    Ok(Some(models::EnergyReport {
+        house: tree.house,
+        from,
+        to: until,
+        usage: house_usage,
+        rooms,
+    }))
+}
+
+/// House the room belongs to, whether or not the room is in the trash.
+fn house_of_room(conn: &mut SqliteConnection, room_id: &str) -> Result<String, AppError> {
+    use crate::schema::rooms::dsl::*;
+
+    Ok(rooms.find(room_id).select(house).first::<String>(conn)?)
+}
+
+fn snapshot<T: Serialize>(value: &T) -> Result<models::Snapshot, AppError> {
+    serde_json::to_value(value)
+        .map(models::Snapshot)
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Appends a change of an entity in a house to the audit log and hands it to
+/// the actor for live subscribers.
+///
+/// `old_value` is absent for creations and `new_value` for deletions.
+fn record_event<T: Item + Serialize>(
+    conn: &mut SqliteConnection,
+    actor: &models::Actor,
+    action: EventAction,
+    house_id: &str,
+    old_value: Option<&T>,
+    new_value: Option<&T>,
+) -> Result<(), AppError> {
+    use crate::schema::events;
+
+    let Some(entity) = new_value.or(old_value) else {
+        return Ok(());
+    };
+    let event = models::NewEvent {
+        at: unix_now(),
+        action,
+        entity: T::KIND,
+        entity_id: entity.id(),
+        house: house_id.to_owned(),
+        client: actor.client.clone(),
+        old_value: old_value.map(snapshot).transpose()?,
+        new_value: new_value.map(snapshot).transpose()?,
+    };
+    let event = diesel::insert_into(events::table)
+        .values(&event)
+        .get_result::<models::Event>(conn)?;
+    let mut change = models::Change::from_event(&event);
+    if T::KIND == models::Device::KIND && change.rooms.len() > 1 {
+        // a device moved from a room of another house leaves that house
+        for room in &change.rooms {
+            let house = house_of_room(conn, room)?;
+            if house != change.house {
+                change.previous_house = Some(house);
+            }
+        }
+    }
+    actor.record(change);
+
+    Ok(())
+}
+
+/// Remember that a device was switched, for the on-time of energy reports.
+fn record_state_change(
+    conn: &mut SqliteConnection,
+    device_id: &str,
+    new_state: bool,
+) -> Result<(), AppError> {
+    use crate::schema::state_changes::dsl::*;
+
+    diesel::insert_into(state_changes)
+        .values(&models::StateSample {
+            device: device_id.to_owned(),
+            state: new_state,
+            changed_at: unix_now(),
+        })
+        .execute(conn)?;
+
+    Ok(())
 }
 
 /// Run query using Diesel to insert a new database row and return the result.
 pub fn insert_new_device(
     conn: &mut SqliteConnection,
     nm: &str,
-    tp: &str,
+    tp: models::DeviceKind,
     adrs: &str,
     rm: &str,
-) -> Result<models::Device, DbError> {
+    actor: &models::Actor,
+) -> Result<models::Device, AppError> {
     // It is common when using Diesel with Actix Web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
     use crate::schema::devices::dsl::*;
+    use crate::schema::rooms::dsl as rms;
 
     let new_device = models::Device {
         id: Uuid::new_v4().to_string(),
         name: nm.to_owned(),
-        type_: tp.to_owned(),
+        type_: tp,
         address: Some(adrs.to_owned()),
         room: rm.to_owned(),
         state: false,
         variable: 0,
+        version: 1,
+        deleted_at: None,
     };
 
-    diesel::insert_into(devices)
-        .values(&new_device)
-        .execute(conn)?;
+    conn.immediate_transaction(|conn| {
+        // the foreign key alone would accept a room in the trash
+        let room_exists = diesel::select(diesel::dsl::exists(
+            rms::rooms
+                .filter(rms::id.eq(rm))
+                .filter(rms::deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if !room_exists {
+            return Err(AppError::ForeignKey(format!(
+                "No room found with UID: {rm}"
+            )));
+        }
+
+        let name_taken = diesel::select(diesel::dsl::exists(
+            devices
+                .filter(room.eq(rm))
+                .filter(name.eq(nm))
+                .filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "Room {rm} already has a device named {nm}"
+            )));
+        }
+
+        diesel::insert_into(devices)
+            .values(&new_device)
+            .execute(conn)?;
+        record_state_change(conn, &new_device.id, new_device.state)?;
+        let house_id = house_of_room(conn, rm)?;
+        record_event(
+            conn,
+            actor,
+            EventAction::Create,
+            &house_id,
+            None,
+            Some(&new_device),
+        )?;
 
-    Ok(new_device)
+        Ok(new_device)
+    })
 }
 
-/// Run query using Diesel to insert a new database row and return the result.
+/// Run query using Diesel to toggle the state of a device and return it.
 pub fn update_state_device(
     conn: &mut SqliteConnection,
     uid: Uuid,
-) -> Result<Option<models::Device>, DbError> {
-    // It is common when using Diesel with Actix Web to import schema-related
-    // modules inside a function's scope (rather than the normal module's scope)
-    // to prevent import collisions and namespace pollution.
+    drivers: &Drivers,
+    actor: &models::Actor,
+) -> Result<Option<models::Device>, AppError> {
+    let Some(device) = find_device_by_id(conn, uid)? else {
+        return Ok(None);
+    };
+
+    // the target is computed once, so the state driven is the state stored
+    let change = set_state_device(conn, uid, !device.state, None, drivers, actor)?;
+
+    Ok(change.map(|change| change.device))
+}
+
+/// Drives a device back to its stored state after a switch to `driven` could
+/// not be stored, so the hardware and the database agree again.
+fn undo_switch(conn: &mut SqliteConnection, drivers: &Drivers, uid: Uuid, driven: bool) {
+    let current = match find_device_by_id(conn, uid) {
+        Ok(Some(current)) => current,
+        // nothing left to agree with
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("device {uid} may have been left switched: {e}");
+            return;
+        }
+    };
+    if current.state == driven {
+        return;
+    }
+
+    if let Err(e) = drivers
+        .for_device(&current)
+        .set_state(&current, current.state)
+    {
+        log::error!(
+            "device {uid} is left {0} while it is stored {1}: {e}",
+            if driven { "on" } else { "off" },
+            if current.state { "on" } else { "off" }
+        );
+    }
+}
+
+/// Rejects a device switched or changed by someone else while its driver was
+/// called, since it can't tell which change the hardware ended up with.
+fn check_unchanged(
+    seen: &models::Device,
+    current: &models::Device,
+    expected_versions: Option<&[i32]>,
+) -> Result<(), AppError> {
+    if current.version == seen.version {
+        return Ok(());
+    }
+
+    match expected_versions {
+        Some(_) => Err(AppError::PreconditionFailed {
+            current_version: current.version,
+        }),
+        None => Err(AppError::Conflict(format!(
+            "Device {0} was changed while it was being switched",
+            current.id
+        ))),
+    }
+}
+
+/// Run query using Diesel to set the state of a device.
+///
+/// The device is left untouched when it already is in the target state. When
+/// `expected_versions` is given the change is only applied if the device is
+/// still at one of these versions. The device is switched through its driver
+/// before the change is stored, with no transaction open while the driver
+/// waits for it, and switched back when the change can't be stored.
+pub fn set_state_device(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    target: bool,
+    expected_versions: Option<&[i32]>,
+    drivers: &Drivers,
+    actor: &models::Actor,
+) -> Result<Option<models::StateChange>, AppError> {
     use crate::schema::devices::dsl::*;
 
-    let device_pre_state = devices
-        .filter(id.eq(uid.to_string()))
-        .first::<models::Device>(conn)
-        .optional()?;
+    let Some(device) = find_device_by_id(conn, uid)? else {
+        return Ok(None);
+    };
 
-    let _ = diesel::update(devices.find(uid.to_string()))
-        .set(state.eq(diesel::dsl::not(state)))
-        .execute(conn);
+    if expected_versions.is_some_and(|expected| !expected.contains(&device.version)) {
+        return Err(AppError::PreconditionFailed {
+            current_version: device.version,
+        });
+    }
 
-    let device = devices
-        .filter(id.eq(uid.to_string()))
-        .first::<models::Device>(conn)
-        .optional()?;
+    if device.state == target {
+        return Ok(Some(models::StateChange {
+            device,
+            changed: false,
+        }));
+    }
 
-    if device.as_ref().unwrap().state != device_pre_state.as_ref().unwrap().state {
-        Ok(devices
-            .filter(id.eq(uid.to_string()))
-            .first::<models::Device>(conn)
-            .optional()?)
-    } else {
-        Err(DbError::from("Error"))
+    drivers.for_device(&device).set_state(&device, target)?;
+
+    // take the write lock up front so that the check and the update can't interleave
+    let stored = conn.immediate_transaction(|conn| {
+        let Some(previous) = find_device_by_id(conn, uid)? else {
+            return Ok(None);
+        };
+        check_unchanged(&device, &previous, expected_versions)?;
+
+        let device = diesel::update(devices.find(uid.to_string()))
+            .set((state.eq(target), version.eq(version + 1)))
+            .get_result::<models::Device>(conn)?;
+        record_state_change(conn, &device.id, device.state)?;
+        let house_id = house_of_room(conn, &device.room)?;
+        record_event(
+            conn,
+            actor,
+            EventAction::State,
+            &house_id,
+            Some(&previous),
+            Some(&device),
+        )?;
+
+        Ok(Some(models::StateChange {
+            device,
+            changed: true,
+        }))
+    });
+    if !matches!(stored, Ok(Some(_))) {
+        undo_switch(conn, drivers, uid, target);
+    }
+
+    stored
+}
+
+/// Checks that a patch can be applied to a device: the target room exists and
+/// holds no other device with the same name.
+fn check_device_patch(
+    conn: &mut SqliteConnection,
+    device: &models::Device,
+    patch: &models::DevicePatch,
+) -> Result<(), AppError> {
+    use crate::schema::devices::dsl::*;
+    use crate::schema::rooms::dsl as rms;
+
+    if let Some(target_room) = &patch.room {
+        let room_exists = diesel::select(diesel::dsl::exists(
+            rms::rooms
+                .filter(rms::id.eq(target_room))
+                .filter(rms::deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if !room_exists {
+            return Err(AppError::ForeignKey(format!(
+                "No room found with UID: {target_room}"
+            )));
+        }
+    }
+
+    let target_name = patch.name.as_ref().unwrap_or(&device.name);
+    let target_room = patch.room.as_ref().unwrap_or(&device.room);
+    let name_taken = diesel::select(diesel::dsl::exists(
+        devices
+            .filter(room.eq(target_room))
+            .filter(name.eq(target_name))
+            .filter(id.ne(&device.id))
+            .filter(deleted_at.is_null()),
+    ))
+    .get_result::<bool>(conn)?;
+    if name_taken {
+        return Err(AppError::Conflict(format!(
+            "Room {target_room} already has a device named {target_name}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Run query using Diesel to apply a partial update to a device and return it.
+///
+/// Renaming or moving a device fails when the target room does not exist or
+/// already holds a device with the same name. A new state is applied through
+/// the driver of the device first, with no transaction open while the driver
+/// waits for it, and undone when the patch can't be stored.
+pub fn update_device(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    patch: &models::DevicePatch,
+    drivers: &Drivers,
+    actor: &models::Actor,
+) -> Result<Option<models::Device>, AppError> {
+    use crate::schema::devices::dsl::*;
+
+    let Some(device) = conn.transaction(|conn| {
+        let Some(device) = find_device_by_id(conn, uid)? else {
+            return Ok::<_, AppError>(None);
+        };
+        if !patch.is_empty() {
+            check_device_patch(conn, &device, patch)?;
+        }
+
+        Ok(Some(device))
+    })?
+    else {
+        return Ok(None);
+    };
+
+    if patch.is_empty() {
+        return Ok(Some(device));
+    }
+
+    let driven = patch.state.filter(|target| *target != device.state);
+    if let Some(target) = driven {
+        drivers.for_device(&device).set_state(&device, target)?;
+    }
+
+    let stored = conn.immediate_transaction(|conn| {
+        let Some(previous) = find_device_by_id(conn, uid)? else {
+            return Ok(None);
+        };
+        check_unchanged(&device, &previous, None)?;
+        // another device may have taken the name meanwhile
+        check_device_patch(conn, &previous, patch)?;
+
+        let device = diesel::update(devices.find(&previous.id))
+            .set((patch, version.eq(version + 1)))
+            .get_result::<models::Device>(conn)?;
+        if device.state != previous.state {
+            record_state_change(conn, &device.id, device.state)?;
+        }
+        let house_id = house_of_room(conn, &device.room)?;
+        record_event(
+            conn,
+            actor,
+            EventAction::Update,
+            &house_id,
+            Some(&previous),
+            Some(&device),
+        )?;
+
+        Ok(Some(device))
+    });
+    if let Some(target) = driven.filter(|_| !matches!(stored, Ok(Some(_)))) {
+        undo_switch(conn, drivers, uid, target);
     }
+
+    stored
+}
+
+/// Run query using Diesel to apply a partial update to a room and return it.
+///
+/// Renaming or moving a room fails when the target house does not exist or
+/// already has a room with the same name.
+pub fn update_room(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    patch: &models::RoomPatch,
+    actor: &models::Actor,
+) -> Result<Option<models::Room>, AppError> {
+    use crate::schema::houses::dsl as hs;
+    use crate::schema::rooms::dsl::*;
+
+    conn.immediate_transaction(|conn| {
+        let Some(other_room) = rooms
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_null())
+            .first::<models::Room>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        if patch.is_empty() {
+            return Ok(Some(other_room));
+        }
+
+        if let Some(target_house) = &patch.house {
+            let house_exists = diesel::select(diesel::dsl::exists(
+                hs::houses
+                    .filter(hs::id.eq(target_house))
+                    .filter(hs::deleted_at.is_null()),
+            ))
+            .get_result::<bool>(conn)?;
+            if !house_exists {
+                return Err(AppError::ForeignKey(format!(
+                    "No house found with UID: {target_house}"
+                )));
+            }
+        }
+
+        let target_name = patch.name.as_ref().unwrap_or(&other_room.name);
+        let target_house = patch.house.as_ref().unwrap_or(&other_room.house);
+        let name_taken = diesel::select(diesel::dsl::exists(
+            rooms
+                .filter(house.eq(target_house))
+                .filter(name.eq(target_name))
+                .filter(id.ne(&other_room.id))
+                .filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "House {target_house} already has a room named {target_name}"
+            )));
+        }
+
+        let previous = other_room;
+        let other_room = diesel::update(rooms.find(&previous.id))
+            .set(patch)
+            .get_result::<models::Room>(conn)?;
+        record_event(
+            conn,
+            actor,
+            EventAction::Update,
+            &other_room.house,
+            Some(&previous),
+            Some(&other_room),
+        )?;
+
+        Ok(Some(other_room))
+    })
+}
+
+/// Run query using Diesel to apply a partial update to a house and return it.
+///
+/// Renaming a house fails when another house already has the same name.
+pub fn update_house(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    patch: &models::HousePatch,
+    actor: &models::Actor,
+) -> Result<Option<models::House>, AppError> {
+    use crate::schema::houses::dsl::*;
+
+    conn.immediate_transaction(|conn| {
+        let Some(other_house) = houses
+            .filter(id.eq(uid.to_string()))
+            .filter(deleted_at.is_null())
+            .first::<models::House>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let Some(target_name) = &patch.name else {
+            return Ok(Some(other_house));
+        };
+
+        let name_taken = diesel::select(diesel::dsl::exists(
+            houses
+                .filter(name.eq(target_name))
+                .filter(id.ne(&other_house.id))
+                .filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "A house named {target_name} already exists"
+            )));
+        }
+
+        let previous = other_house;
+        let other_house = diesel::update(houses.find(&previous.id))
+            .set(patch)
+            .get_result::<models::House>(conn)?;
+        record_event(
+            conn,
+            actor,
+            EventAction::Update,
+            &other_house.id,
+            Some(&previous),
+            Some(&other_house),
+        )?;
+
+        Ok(Some(other_house))
+    })
 }
 
 /// Run query using Diesel to insert a new database row and return the result.
@@ -266,28 +1719,61 @@ pub fn insert_new_room(
     conn: &mut SqliteConnection,
     nm: &str,
     hs: &str,
-) -> Result<models::Room, DbError> {
+    actor: &models::Actor,
+) -> Result<models::Room, AppError> {
     // It is common when using Diesel with Actix Web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
+    use crate::schema::houses;
     use crate::schema::rooms::dsl::*;
 
     let new_room = models::Room {
         id: Uuid::new_v4().to_string(),
         name: String::from(nm),
         house: String::from(hs),
+        deleted_at: None,
     };
 
-    println!("Trying insert room {}", new_room.house.len());
+    conn.immediate_transaction(|conn| {
+        // the foreign key alone would accept a house in the trash
+        let house_exists = diesel::select(diesel::dsl::exists(
+            houses::table
+                .filter(houses::id.eq(hs))
+                .filter(houses::deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if !house_exists {
+            return Err(AppError::ForeignKey(format!(
+                "No house found with UID: {hs}"
+            )));
+        }
 
-    //diesel::insert_into(rooms).values(&new_room).execute(conn)?;
-    diesel::insert_into(rooms).values(&new_room).execute(conn)?;
+        let name_taken = diesel::select(diesel::dsl::exists(
+            rooms
+                .filter(house.eq(hs))
+                .filter(name.eq(nm))
+                .filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "House {hs} already has a room named {nm}"
+            )));
+        }
 
-    Ok(new_room)
+        diesel::insert_into(rooms).values(&new_room).execute(conn)?;
+        record_event(conn, actor, EventAction::Create, hs, None, Some(&new_room))?;
+
+        Ok(new_room)
+    })
 }
 
 /// Run query using Diesel to insert a new database row and return the result.
-pub fn insert_new_house(conn: &mut SqliteConnection, nm: &str) -> Result<models::House, DbError> {
+pub fn insert_new_house(
+    conn: &mut SqliteConnection,
+    nm: &str,
+    actor: &models::Actor,
+) -> Result<models::House, AppError> {
     // It is common when using Diesel with Actix Web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
@@ -296,11 +1782,287 @@ pub fn insert_new_house(conn: &mut SqliteConnection, nm: &str) -> Result<models:
     let new_house = models::House {
         id: Uuid::new_v4().to_string(),
         name: nm.to_owned(),
+        deleted_at: None,
     };
 
-    diesel::insert_into(houses)
-        .values(&new_house)
+    // take the write lock up front so that the check and the insert can't interleave
+    conn.immediate_transaction(|conn| {
+        let name_taken = diesel::select(diesel::dsl::exists(
+            houses.filter(name.eq(nm)).filter(deleted_at.is_null()),
+        ))
+        .get_result::<bool>(conn)?;
+        if name_taken {
+            return Err(AppError::Conflict(format!(
+                "A house named {nm} already exists"
+            )));
+        }
+
+        diesel::insert_into(houses)
+            .values(&new_house)
+            .execute(conn)?;
+        record_event(
+            conn,
+            actor,
+            EventAction::Create,
+            &new_house.id,
+            None,
+            Some(&new_house),
+        )?;
+
+        Ok(new_house)
+    })
+}
+
+/// Run query using Diesel to list audit log events matching the query, oldest first.
+///
+/// The cursor of a page is the id of its last event.
+pub fn list_events(
+    conn: &mut SqliteConnection,
+    query: &models::EventQuery,
+) -> Result<models::Page<models::Event>, AppError> {
+    use crate::schema::events::dsl::*;
+
+    let mut select = events.into_boxed();
+    if let Some(cursor) = query.cursor {
+        // event ids are handed out as cursors, so anything wider is made up
+        let after = i32::try_from(cursor.0)
+            .map_err(|_| AppError::Validation(String::from("Invalid cursor")))?;
+        select = select.filter(id.gt(after));
+    }
+    if let Some(kind) = &query.entity {
+        select = select.filter(entity.eq(kind));
+    }
+    if let Some(uid) = query.entity_id {
+        select = select.filter(entity_id.eq(uid.to_string()));
+    }
+    if let Some(uid) = query.house {
+        select = select.filter(house.eq(uid.to_string()));
+    }
+    if let Some(kind) = query.action {
+        select = select.filter(action.eq(kind));
+    }
+    if let Some(name) = &query.client {
+        select = select.filter(client.eq(name));
+    }
+    if let Some(from) = query.from {
+        select = select.filter(at.ge(from));
+    }
+    if let Some(to) = query.to {
+        select = select.filter(at.lt(to));
+    }
+
+    let limit = query.limit();
+    let mut items = select
+        .order(id)
+        .limit(limit + 1)
+        .load::<models::Event>(conn)?;
+    let next_cursor = (items.len() as i64 > limit).then(|| {
+        items.truncate(limit as usize);
+        models::Cursor(items.last().map_or(0, |event| i64::from(event.id)))
+    });
+
+    Ok(models::Page { items, next_cursor })
+}
+
+/// Run query using Diesel to find an audit log event by id.
+pub fn find_event_by_id(
+    conn: &mut SqliteConnection,
+    event_id: i32,
+) -> Result<Option<models::Event>, AppError> {
+    use crate::schema::events::dsl::*;
+
+    Ok(events
+        .find(event_id)
+        .first::<models::Event>(conn)
+        .optional()?)
+}
+
+/// Run query using Diesel to list the latest readings of a device.
+pub fn list_readings(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+) -> Result<Vec<models::Reading>, AppError> {
+    use crate::schema::readings::dsl::*;
+
+    let device_readings = readings
+        .filter(device.eq(uid.to_string()))
+        .order(metric.asc())
+        .load::<models::Reading>(conn)?;
+
+    Ok(device_readings)
+}
+
+/// Store new readings of a device.
+///
+/// Every reading is appended to the history; the latest value of each metric
+/// replaces the previous one. The primary metric of the device kind is mirrored
+/// into the legacy `variable` column so that `/device/{uid}/var` keeps reporting it.
+/// The stored readings are handed to the actor for live subscribers.
+pub fn record_readings(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    new_readings: &[models::NewReading],
+    actor: &models::Actor,
+) -> Result<Option<Vec<models::Reading>>, AppError> {
+    use crate::schema::devices::dsl as dvs;
+    use crate::schema::reading_history::dsl as hst;
+    use crate::schema::readings::dsl as rds;
+
+    conn.immediate_transaction(|conn| {
+        let Some(device) = dvs::devices
+            .filter(dvs::id.eq(uid.to_string()))
+            .filter(dvs::deleted_at.is_null())
+            .first::<models::Device>(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let now = unix_now();
+        let mut stored = Vec::with_capacity(new_readings.len());
+        for new_reading in new_readings {
+            let recorded_at = new_reading.recorded_at.unwrap_or(now);
+            if recorded_at > now + MAX_CLOCK_SKEW {
+                return Err(AppError::Validation(format!(
+                    "Reading of {0} is recorded in the future",
+                    new_reading.metric
+                )));
+            }
+
+            let reading = models::Reading {
+                device: device.id.clone(),
+                metric: new_reading.metric,
+                value: new_reading.value,
+                unit: new_reading.validate(device.type_)?.to_owned(),
+                updated_at: recorded_at,
+            };
+
+            diesel::insert_into(hst::reading_history)
+                .values(&models::HistorySample {
+                    device: reading.device.clone(),
+                    metric: reading.metric,
+                    value: reading.value,
+                    unit: reading.unit.clone(),
+                    recorded_at,
+                })
+                .execute(conn)?;
+
+            // late samples only go to the history
+            let latest = rds::readings
+                .find((&reading.device, reading.metric))
+                .select(rds::updated_at)
+                .first::<i64>(conn)
+                .optional()?;
+            if latest.is_some_and(|latest| latest > recorded_at) {
+                stored.push(reading);
+                continue;
+            }
+
+            diesel::replace_into(rds::readings)
+                .values(&reading)
+                .execute(conn)?;
+
+            if device.type_.primary_metric() == Some(reading.metric) {
+                diesel::update(dvs::devices.find(&device.id))
+                    .set(dvs::variable.eq(reading.value.round() as i32))
+                    .execute(conn)?;
+            }
+
+            stored.push(reading);
+        }
+
+        if !stored.is_empty() {
+            let house_id = house_of_room(conn, &device.room)?;
+            actor.record(models::Change::readings(&device, &house_id, &stored));
+        }
+
+        Ok(Some(stored))
+    })
+}
+
+/// Run query using Diesel to downsample the history of one metric of a device.
+///
+/// Readings in `[from, to)` are grouped into `step` seconds wide buckets. When no
+/// metric is given the primary metric of the device kind is used.
+pub fn get_device_history(
+    conn: &mut SqliteConnection,
+    uid: Uuid,
+    metric: Option<models::Metric>,
+    from: i64,
+    to: i64,
+    step: i64,
+) -> Result<Option<models::History>, AppError> {
+    use diesel::sql_types::{BigInt, Text};
+
+    let Some(device) = find_device_by_id(conn, uid)? else {
+        return Ok(None);
+    };
+
+    let metric = match metric.or(device.type_.primary_metric()) {
+        Some(metric) if device.type_.metrics().contains(&metric) => metric,
+        _ => {
+            return Err(AppError::Validation(format!(
+                "Device of type {0} has no such metric",
+                device.type_
+            )))
+        }
+    };
+
+    let points = diesel::sql_query(
+        "SELECT (recorded_at / ?1) * ?1 AS time, unit, \
+                MIN(value) AS min, MAX(value) AS max, AVG(value) AS avg, COUNT(*) AS samples \
+         FROM reading_history \
+         WHERE device = ?2 AND metric = ?3 AND recorded_at >= ?4 AND recorded_at < ?5 \
+         GROUP BY time, unit \
+         ORDER BY time, unit",
+    )
+    .bind::<BigInt, _>(step)
+    .bind::<Text, _>(&device.id)
+    .bind::<Text, _>(metric.as_str())
+    .bind::<BigInt, _>(from)
+    .bind::<BigInt, _>(to)
+    .load::<models::HistoryBucket>(conn)?;
+
+    Ok(Some(models::History {
+        device: device.id,
+        metric,
+        from,
+        to,
+        step,
+        points,
+    }))
+}
+
+/// Delete history samples which fall outside of the retention policy and
+/// return how many were removed.
+pub fn apply_history_retention(
+    conn: &mut SqliteConnection,
+    policy: &crate::retention::RetentionPolicy,
+) -> Result<usize, AppError> {
+    use crate::schema::reading_history::dsl::*;
+    use diesel::sql_types::BigInt;
+
+    let mut removed = 0;
+
+    if let Some(max_age) = policy.max_age {
+        let cutoff = unix_now() - max_age.as_secs() as i64;
+        removed += diesel::delete(reading_history.filter(recorded_at.lt(cutoff))).execute(conn)?;
+    }
+
+    if let Some(max_samples) = policy.max_samples {
+        removed += diesel::sql_query(
+            "DELETE FROM reading_history WHERE id IN ( \
+                SELECT id FROM ( \
+                    SELECT id, ROW_NUMBER() OVER ( \
+                        PARTITION BY device, metric ORDER BY recorded_at DESC, id DESC \
+                    ) AS position \
+                    FROM reading_history \
+                ) WHERE position > ? \
+            )",
+        )
+        .bind::<BigInt, _>(max_samples)
         .execute(conn)?;
+    }
 
-    Ok(new_house)
+    Ok(removed)
 }
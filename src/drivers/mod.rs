@@ -0,0 +1,366 @@
+use crate::live::{ChangeHub, Publish};
+use crate::models::{self, Device, DeviceKind, HealthStatus, NewReading};
+use crate::{actions, DbPool};
+use actix::Addr;
+use actix_web::{rt, web};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+mod tcp;
+
+pub use tcp::TcpSocketDriver;
+
+/// Address of a device that only exists in the database.
+pub const VIRTUAL_ADDRESS: &str = "virtual";
+
+/// Why a device could not be driven.
+#[derive(Debug)]
+pub struct DriverError(pub String);
+
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for DriverError {}
+
+/// Talks to the hardware behind a device at its address.
+///
+/// Calls block until the device answers or gives up, so they are made from
+/// `web::block` like the database queries.
+pub trait DeviceDriver: Send + Sync {
+    /// Short name reported by health checks, e.g. `virtual`.
+    fn name(&self) -> &'static str;
+
+    /// Opens a connection to the device, failing when it can't be reached.
+    fn connect(&self, device: &Device) -> Result<(), DriverError>;
+
+    /// Switches the device on or off.
+    fn set_state(&self, device: &Device, on: bool) -> Result<(), DriverError>;
+
+    /// Reads the current values of the metrics the device reports.
+    fn read_value(&self, device: &Device) -> Result<Vec<NewReading>, DriverError>;
+
+    /// Whether the device answers.
+    fn health(&self, device: &Device) -> Result<HealthStatus, DriverError> {
+        self.connect(device).map(|()| HealthStatus::Online)
+    }
+}
+
+/// Keeps the state and readings of a device in the database only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VirtualDriver;
+
+impl DeviceDriver for VirtualDriver {
+    fn name(&self) -> &'static str {
+        "virtual"
+    }
+
+    fn connect(&self, _: &Device) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    fn set_state(&self, _: &Device, _: bool) -> Result<(), DriverError> {
+        Ok(())
+    }
+
+    fn read_value(&self, _: &Device) -> Result<Vec<NewReading>, DriverError> {
+        Ok(Vec::new())
+    }
+
+    fn health(&self, _: &Device) -> Result<HealthStatus, DriverError> {
+        Ok(HealthStatus::Virtual)
+    }
+}
+
+/// Driver by its name in `DEVICE_DRIVERS`.
+fn driver_by_name(name: &str, timeout: Duration) -> Option<Arc<dyn DeviceDriver>> {
+    match name {
+        "virtual" => Some(Arc::new(VirtualDriver)),
+        "tcp" => Some(Arc::new(TcpSocketDriver { timeout })),
+        _ => None,
+    }
+}
+
+/// Drivers selected by device kind.
+///
+/// Devices of kinds without a driver, or without an address or with the
+/// address `virtual`, are virtual.
+///
+/// Read from the environment:
+/// - `DEVICE_DRIVERS`: comma separated `kind=driver` pairs, e.g. `socket=tcp`
+///   (unset makes every device virtual)
+/// - `DEVICE_TIMEOUT_MS`: how long a driver waits for a device (default 2000)
+/// - `DEVICE_POLL_INTERVAL_SECS`: how often readings are polled from devices with
+///   a driver (default 60)
+#[derive(Clone)]
+pub struct Drivers {
+    by_kind: HashMap<DeviceKind, Arc<dyn DeviceDriver>>,
+    pub poll_interval: Duration,
+}
+
+impl Default for Drivers {
+    fn default() -> Self {
+        Self {
+            by_kind: HashMap::new(),
+            poll_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl fmt::Debug for Drivers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let by_kind: HashMap<_, _> = self
+            .by_kind
+            .iter()
+            .map(|(kind, driver)| (kind, driver.name()))
+            .collect();
+        f.debug_struct("Drivers")
+            .field("by_kind", &by_kind)
+            .field("poll_interval", &self.poll_interval)
+            .finish()
+    }
+}
+
+impl Drivers {
+    pub fn from_env() -> Self {
+        let mut drivers = Self::default();
+
+        let mut timeout = TcpSocketDriver::default().timeout;
+        if let Ok(millis) = std::env::var("DEVICE_TIMEOUT_MS") {
+            match millis.parse::<u64>() {
+                Ok(millis) => timeout = Duration::from_millis(millis.max(1)),
+                Err(_) => log::warn!("ignoring DEVICE_TIMEOUT_MS={millis}: not a number"),
+            }
+        }
+        if let Ok(value) = std::env::var("DEVICE_DRIVERS") {
+            for pair in value
+                .split(',')
+                .map(str::trim)
+                .filter(|pair| !pair.is_empty())
+            {
+                let Some((kind, name)) = pair.split_once('=') else {
+                    log::warn!("ignoring DEVICE_DRIVERS entry {pair}: expected kind=driver");
+                    continue;
+                };
+                match (
+                    kind.trim().parse::<DeviceKind>(),
+                    driver_by_name(name.trim(), timeout),
+                ) {
+                    (Ok(kind), Some(driver)) => drivers = drivers.with(kind, driver),
+                    (Err(e), _) => log::warn!("ignoring DEVICE_DRIVERS entry {pair}: {e}"),
+                    (_, None) => log::warn!("ignoring DEVICE_DRIVERS entry {pair}: unknown driver"),
+                }
+            }
+        }
+        if let Ok(secs) = std::env::var("DEVICE_POLL_INTERVAL_SECS") {
+            match secs.parse::<u64>() {
+                Ok(secs) => drivers.poll_interval = Duration::from_secs(secs.max(1)),
+                Err(_) => log::warn!("ignoring DEVICE_POLL_INTERVAL_SECS={secs}: not a number"),
+            }
+        }
+
+        drivers
+    }
+
+    /// Drives devices of `kind` through `driver`.
+    pub fn with(mut self, kind: DeviceKind, driver: Arc<dyn DeviceDriver>) -> Self {
+        self.by_kind.insert(kind, driver);
+        self
+    }
+
+    /// Kinds of devices with a driver.
+    pub fn kinds(&self) -> Vec<DeviceKind> {
+        self.by_kind.keys().copied().collect()
+    }
+
+    /// Driver of a device, the virtual one when the device has no hardware behind it.
+    pub fn for_device(&self, device: &Device) -> &dyn DeviceDriver {
+        let address = device.address.as_deref().map(str::trim).unwrap_or_default();
+        if address.is_empty() || address == VIRTUAL_ADDRESS {
+            return &VirtualDriver;
+        }
+
+        match self.by_kind.get(&device.type_) {
+            Some(driver) => driver.as_ref(),
+            None => &VirtualDriver,
+        }
+    }
+
+    /// Checks whether the hardware behind a device answers.
+    pub fn health(&self, device: &Device) -> models::DeviceHealth {
+        let driver = self.for_device(device);
+        let (status, error) = match driver.health(device) {
+            Ok(status) => (status, None),
+            Err(e) => (HealthStatus::Offline, Some(e.to_string())),
+        };
+
+        models::DeviceHealth {
+            device: device.id.clone(),
+            driver: driver.name().to_owned(),
+            status,
+            error,
+            checked_at: actions::unix_now(),
+        }
+    }
+}
+
+/// Reads the values of every device with a driver and stores them, returning
+/// the changes for live subscribers.
+///
+/// A device that can't be read is logged and skipped.
+pub fn poll_devices(
+    conn: &mut diesel::SqliteConnection,
+    drivers: &Drivers,
+) -> Result<Vec<models::Change>, crate::error::AppError> {
+    let actor = models::Actor::new("driver");
+
+    for device in actions::list_devices_of_kinds(conn, &drivers.kinds())? {
+        let driver = drivers.for_device(&device);
+        let readings = match driver.read_value(&device) {
+            Ok(readings) if readings.is_empty() => continue,
+            Ok(readings) => readings,
+            Err(e) => {
+                log::warn!("reading device {0} failed: {e}", device.id);
+                continue;
+            }
+        };
+
+        let Ok(uid) = Uuid::parse_str(&device.id) else {
+            continue;
+        };
+        if let Err(e) = actions::record_readings(conn, uid, &readings, &actor) {
+            log::warn!("storing readings of device {0} failed: {e}", device.id);
+        }
+    }
+
+    Ok(actor.take_changes())
+}
+
+/// Periodically polls the readings of devices with a driver.
+pub fn spawn(pool: DbPool, drivers: Drivers, hub: Addr<ChangeHub>) {
+    if drivers.by_kind.is_empty() {
+        return;
+    }
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(drivers.poll_interval);
+
+        loop {
+            interval.tick().await;
+
+            let pool = pool.clone();
+            let drivers = drivers.clone();
+            let changes = web::block(move || {
+                let mut conn = pool.get()?;
+
+                poll_devices(&mut conn, &drivers)
+            })
+            .await;
+
+            match changes {
+                Ok(Ok(changes)) if changes.is_empty() => {}
+                Ok(Ok(changes)) => hub.do_send(Publish(changes)),
+                Ok(Err(e)) => log::error!("polling devices failed: {e}"),
+                Err(e) => log::error!("polling devices failed: {e}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Metric;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    fn device(kind: DeviceKind, address: &str) -> Device {
+        Device {
+            id: String::from("d1"),
+            name: String::from("Kettle"),
+            type_: kind,
+            address: Some(String::from(address)),
+            state: false,
+            variable: 0,
+            room: String::from("r1"),
+            version: 1,
+            deleted_at: None,
+        }
+    }
+
+    /// Listens on a free port, answering each command with `reply`.
+    fn socket(reply: fn(&str) -> &'static str) -> String {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut replies = stream.try_clone().unwrap();
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    writeln!(replies, "{0}", reply(line.trim())).unwrap();
+                }
+            }
+        });
+
+        address
+    }
+
+    #[test]
+    fn tcp_socket_driver() {
+        let driver = TcpSocketDriver::default();
+        let address = socket(|command| match command {
+            "on" | "off" => "ok",
+            "status" => "on",
+            "power" => "1250.5",
+            _ => "err unknown command",
+        });
+        let kettle = device(DeviceKind::Socket, &address);
+
+        driver.set_state(&kettle, true).unwrap();
+        driver.set_state(&kettle, false).unwrap();
+        assert_eq!(driver.health(&kettle).unwrap(), HealthStatus::Online);
+        let readings = driver.read_value(&kettle).unwrap();
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].metric, Metric::Power);
+        assert_eq!(readings[0].value, 1250.5);
+        assert_eq!(readings[0].unit.as_deref(), Some("W"));
+
+        // refusals and nonsense are errors
+        let address = socket(|command| match command {
+            "power" => "a lot",
+            _ => "err overheated",
+        });
+        let broken = device(DeviceKind::Socket, &address);
+        let e = driver.set_state(&broken, true).unwrap_err();
+        assert_eq!(e.to_string(), "Socket refused on: overheated");
+        assert!(driver.read_value(&broken).is_err());
+        assert!(driver.health(&broken).is_err());
+    }
+
+    #[test]
+    fn drivers_by_kind() {
+        let drivers =
+            Drivers::default().with(DeviceKind::Socket, Arc::new(TcpSocketDriver::default()));
+
+        let name = |kind, address| drivers.for_device(&device(kind, address)).name();
+        assert_eq!(name(DeviceKind::Socket, "127.0.0.1:7890"), "tcp");
+        assert_eq!(name(DeviceKind::Socket, VIRTUAL_ADDRESS), "virtual");
+        assert_eq!(name(DeviceKind::Socket, " "), "virtual");
+        assert_eq!(name(DeviceKind::Lamp, "127.0.0.1:7890"), "virtual");
+
+        // nothing listens on the port of a listener which was closed again
+        let address = TcpListener::bind(("127.0.0.1", 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .to_string();
+        let health = drivers.health(&device(DeviceKind::Socket, &address));
+        assert_eq!(health.status, HealthStatus::Offline);
+        assert!(health.error.is_some());
+    }
+}
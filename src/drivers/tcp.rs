@@ -0,0 +1,137 @@
+use super::{DeviceDriver, DriverError};
+use crate::models::{Device, HealthStatus, Metric, NewReading};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Port of a smart socket whose address doesn't name one.
+pub const DEFAULT_PORT: u16 = 7890;
+
+/// Smart socket speaking a line based protocol over TCP.
+///
+/// Every command is a line sent to the socket, answered with one line:
+///
+/// | command  | reply                          |
+/// |----------|--------------------------------|
+/// | `on`     | `ok`                           |
+/// | `off`    | `ok`                           |
+/// | `status` | `on` or `off`                  |
+/// | `power`  | watts drawn, e.g. `1250.5`     |
+///
+/// A command the socket can't carry out is answered with `err <reason>`.
+/// The address of the device is `host` or `host:port`.
+#[derive(Debug, Clone, Copy)]
+pub struct TcpSocketDriver {
+    /// How long connecting, sending and waiting for a reply may each take.
+    pub timeout: Duration,
+}
+
+impl Default for TcpSocketDriver {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+impl TcpSocketDriver {
+    fn resolve(device: &Device) -> Result<SocketAddr, DriverError> {
+        let address = device.address.as_deref().unwrap_or_default().trim();
+        let resolved = match address.to_socket_addrs() {
+            Ok(addrs) => addrs.into_iter().next(),
+            // no port given
+            Err(_) => (address, DEFAULT_PORT)
+                .to_socket_addrs()
+                .ok()
+                .and_then(|mut addrs| addrs.next()),
+        };
+
+        resolved.ok_or_else(|| DriverError(format!("Invalid socket address: {address}")))
+    }
+
+    fn open(&self, device: &Device) -> Result<TcpStream, DriverError> {
+        let address = Self::resolve(device)?;
+        let unreachable = |e: std::io::Error| DriverError(format!("Socket at {address}: {e}"));
+
+        let stream = TcpStream::connect_timeout(&address, self.timeout).map_err(unreachable)?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .map_err(unreachable)?;
+        stream
+            .set_write_timeout(Some(self.timeout))
+            .map_err(unreachable)?;
+
+        Ok(stream)
+    }
+
+    /// Sends a command and returns the reply without the line ending.
+    fn command(&self, device: &Device, command: &str) -> Result<String, DriverError> {
+        let mut stream = self.open(device)?;
+        let failed = |e: std::io::Error| DriverError(format!("Socket command {command}: {e}"));
+
+        stream
+            .write_all(format!("{command}\n").as_bytes())
+            .map_err(failed)?;
+        let mut reply = String::new();
+        BufReader::new(stream)
+            .read_line(&mut reply)
+            .map_err(failed)?;
+
+        let reply = reply.trim();
+        if reply.is_empty() {
+            return Err(DriverError(format!(
+                "Socket command {command}: connection closed"
+            )));
+        }
+        if let Some(reason) = reply.strip_prefix("err") {
+            return Err(DriverError(format!(
+                "Socket refused {command}: {0}",
+                reason.trim()
+            )));
+        }
+
+        Ok(reply.to_owned())
+    }
+}
+
+impl DeviceDriver for TcpSocketDriver {
+    fn name(&self) -> &'static str {
+        "tcp"
+    }
+
+    fn connect(&self, device: &Device) -> Result<(), DriverError> {
+        self.open(device).map(drop)
+    }
+
+    fn set_state(&self, device: &Device, on: bool) -> Result<(), DriverError> {
+        let command = if on { "on" } else { "off" };
+        match self.command(device, command)?.as_str() {
+            "ok" => Ok(()),
+            reply => Err(DriverError(format!(
+                "Unexpected reply to {command}: {reply}"
+            ))),
+        }
+    }
+
+    fn read_value(&self, device: &Device) -> Result<Vec<NewReading>, DriverError> {
+        let reply = self.command(device, "power")?;
+        let watts = reply
+            .parse::<f64>()
+            .map_err(|_| DriverError(format!("Unexpected reply to power: {reply}")))?;
+
+        Ok(vec![NewReading {
+            metric: Metric::Power,
+            value: watts,
+            unit: Some(String::from("W")),
+            recorded_at: None,
+        }])
+    }
+
+    fn health(&self, device: &Device) -> Result<HealthStatus, DriverError> {
+        // a socket which answers its status is up, whatever the state
+        match self.command(device, "status")?.as_str() {
+            "on" | "off" => Ok(HealthStatus::Online),
+            reply => Err(DriverError(format!("Unexpected reply to status: {reply}"))),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::drivers::DriverError;
+use crate::models;
+use crate::report_generator::{DoubleError, UnknownLanguage};
+use actix_web::{error, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use utoipa::ToSchema;
+
+/// Error returned by actions and handlers.
+///
+/// Every variant is rendered as a JSON body of the form
+/// `{"code": ..., "message": ..., "details": ...}` with a matching status code.
+#[derive(Debug)]
+pub enum AppError {
+    /// The entity addressed by the request does not exist.
+    NotFound { entity: &'static str, id: String },
+    /// The request is malformed or breaks a rule of the data model.
+    Validation(String),
+    /// The change clashes with existing data, e.g. a sibling with the same name.
+    Conflict(String),
+    /// A referenced parent entity does not exist.
+    ForeignKey(String),
+    /// The entity changed since the client last saw it.
+    PreconditionFailed { current_version: i32 },
+    /// The device could not be reached or refused the command.
+    Device(String),
+    /// Anything else; the message is logged but not sent to the client.
+    Internal(String),
+}
+
+/// JSON body of an error response.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Value>,
+}
+
+impl AppError {
+    pub fn not_found(entity: &'static str, id: impl fmt::Display) -> Self {
+        AppError::NotFound {
+            entity,
+            id: id.to_string(),
+        }
+    }
+
+    /// Stable machine readable name of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound { .. } => "not_found",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Conflict(_) => "conflict",
+            AppError::ForeignKey(_) => "foreign_key_violation",
+            AppError::PreconditionFailed { .. } => "precondition_failed",
+            AppError::Device(_) => "device_unavailable",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn details(&self) -> Option<Value> {
+        match self {
+            AppError::NotFound { entity, id } => Some(json!({ "entity": entity, "id": id })),
+            AppError::PreconditionFailed { current_version } => {
+                Some(json!({ "current_version": current_version }))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::NotFound { entity, id } => write!(f, "No {entity} found with UID: {id}"),
+            AppError::Validation(message)
+            | AppError::Conflict(message)
+            | AppError::ForeignKey(message)
+            | AppError::Device(message) => f.write_str(message),
+            AppError::PreconditionFailed { current_version } => {
+                write!(f, "Entity is at version {current_version}")
+            }
+            AppError::Internal(_) => f.write_str("Internal server error"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::Conflict(_) | AppError::ForeignKey(_) => StatusCode::CONFLICT,
+            AppError::PreconditionFailed { .. } => StatusCode::PRECONDITION_FAILED,
+            AppError::Device(_) => StatusCode::BAD_GATEWAY,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let AppError::Internal(reason) = self {
+            log::error!("internal error: {reason}");
+        }
+
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code().to_owned(),
+            message: self.to_string(),
+            details: self.details(),
+        })
+    }
+}
+
+impl From<DieselError> for AppError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => AppError::NotFound {
+                entity: "record",
+                id: String::new(),
+            },
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                AppError::ForeignKey(format!(
+                    "Referenced entity does not exist: {0}",
+                    info.message()
+                ))
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                AppError::Conflict(info.message().to_owned())
+            }
+            e => AppError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for AppError {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<error::BlockingError> for AppError {
+    fn from(e: error::BlockingError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<models::UnknownDeviceKind> for AppError {
+    fn from(e: models::UnknownDeviceKind) -> Self {
+        AppError::Validation(e.to_string())
+    }
+}
+
+impl From<models::InvalidReading> for AppError {
+    fn from(e: models::InvalidReading) -> Self {
+        AppError::Validation(e.to_string())
+    }
+}
+
+impl From<models::UnknownField> for AppError {
+    fn from(e: models::UnknownField) -> Self {
+        AppError::Validation(e.to_string())
+    }
+}
+
+impl From<UnknownLanguage> for AppError {
+    fn from(e: UnknownLanguage) -> Self {
+        AppError::Validation(e.to_string())
+    }
+}
+
+impl From<DoubleError> for AppError {
+    fn from(e: DoubleError) -> Self {
+        AppError::Validation(e.to_string())
+    }
+}
+
+impl From<DriverError> for AppError {
+    fn from(e: DriverError) -> Self {
+        AppError::Device(e.to_string())
+    }
+}
+
+/// Reports malformed path parameters, such as an invalid UUID, as not found.
+pub fn path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(|err, req: &HttpRequest| {
+        log::debug!("malformed path {0}: {err}", req.path());
+        AppError::not_found("resource", req.path()).into()
+    })
+}
+
+/// Reports malformed JSON bodies as validation errors.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .error_handler(|err, _req| AppError::Validation(err.to_string()).into())
+}
+
+/// Reports malformed query strings as validation errors.
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default()
+        .error_handler(|err, _req| AppError::Validation(err.to_string()).into())
+}
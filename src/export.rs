@@ -0,0 +1,285 @@
+use crate::error::AppError;
+use crate::models::{ExportFile, ReportFormat};
+use crate::report_generator::{self, Catalog};
+use crate::{actions, DbPool};
+use actix_web::{rt, web};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use cron::Schedule;
+use diesel::SqliteConnection;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Format of the time stamp in export file names, in UTC.
+const STAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+
+/// Which house reports are exported to files, where and when.
+///
+/// Read from the environment:
+/// - `REPORT_EXPORT_DIR`: directory the files are written to (default `exports`)
+/// - `REPORT_EXPORT_SCHEDULE`: cron expression with seconds in UTC, e.g. `0 0 6 * * *`
+///   (unset disables scheduled exports)
+/// - `REPORT_EXPORT_FORMATS`: comma separated formats (default `html`)
+/// - `REPORT_EXPORT_HOUSES`: comma separated house UIDs (default every house)
+/// - `REPORT_EXPORT_LANG`: language of the report text (default `ru`)
+/// - `REPORT_EXPORT_KEEP`: files kept per house and format (default 30)
+#[derive(Debug, Clone)]
+pub struct ExportConfig {
+    pub dir: PathBuf,
+    pub schedule: Option<Schedule>,
+    pub formats: Vec<ReportFormat>,
+    pub houses: Option<Vec<Uuid>>,
+    pub lang: String,
+    pub keep: usize,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("exports"),
+            schedule: None,
+            formats: vec![ReportFormat::Html],
+            houses: None,
+            lang: String::from(report_generator::DEFAULT_LANGUAGE),
+            keep: 30,
+        }
+    }
+}
+
+impl ExportConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(dir) = std::env::var("REPORT_EXPORT_DIR") {
+            config.dir = PathBuf::from(dir);
+        }
+        if let Ok(schedule) = std::env::var("REPORT_EXPORT_SCHEDULE") {
+            match Schedule::from_str(&schedule) {
+                Ok(schedule) => config.schedule = Some(schedule),
+                Err(e) => log::warn!("ignoring REPORT_EXPORT_SCHEDULE={schedule}: {e}"),
+            }
+        }
+        if let Some(formats) = env_list::<ReportFormat>("REPORT_EXPORT_FORMATS") {
+            config.formats = formats;
+        }
+        if let Some(houses) = env_list::<Uuid>("REPORT_EXPORT_HOUSES") {
+            config.houses = Some(houses);
+        }
+        if let Ok(lang) = std::env::var("REPORT_EXPORT_LANG") {
+            match Catalog::find(&lang) {
+                Some(catalog) => config.lang = catalog.lang().to_owned(),
+                None => log::warn!("ignoring REPORT_EXPORT_LANG={lang}: unsupported language"),
+            }
+        }
+        if let Ok(keep) = std::env::var("REPORT_EXPORT_KEEP") {
+            match keep.parse::<usize>() {
+                Ok(keep) => config.keep = keep.max(1),
+                Err(_) => log::warn!("ignoring REPORT_EXPORT_KEEP={keep}: not a number"),
+            }
+        }
+
+        config
+    }
+}
+
+/// Parses a comma separated list; `None` when unset or when an entry is invalid.
+fn env_list<T: for<'de> serde::Deserialize<'de>>(key: &str) -> Option<Vec<T>> {
+    let value = std::env::var(key).ok()?;
+    let items: Result<Vec<T>, _> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| serde_json::from_value(serde_json::Value::String(item.to_owned())))
+        .collect();
+
+    match items {
+        Ok(items) if !items.is_empty() => Some(items),
+        _ => {
+            log::warn!("ignoring {key}={value}: invalid list");
+            None
+        }
+    }
+}
+
+/// Parses the name of an export file; other files in the directory are ignored.
+fn parse_file_name(name: &str) -> Option<(String, ReportFormat, i64)> {
+    let (stem, extension) = name.rsplit_once('.')?;
+    let format = ReportFormat::from_extension(extension)?;
+    let (house, stamp) = stem.split_once('_')?;
+    let house = Uuid::parse_str(house).ok()?;
+    // files made within the same millisecond are numbered
+    let stamp = match stamp.split_once('-') {
+        Some((stamp, number)) => number.parse::<u32>().map(|_| stamp).ok()?,
+        None => stamp,
+    };
+    let created_at = NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT).ok()?;
+
+    Some((
+        house.to_string(),
+        format,
+        created_at.and_utc().timestamp_millis(),
+    ))
+}
+
+/// Lists the export files in the directory, newest first.
+pub fn list_exports(dir: &Path) -> Result<Vec<ExportFile>, AppError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let Some((house, format, created_at)) = parse_file_name(&name) else {
+            continue;
+        };
+        files.push(ExportFile {
+            name,
+            house,
+            format,
+            created_at,
+            size: entry.metadata()?.len(),
+        });
+    }
+
+    files.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.name.cmp(&a.name)));
+    Ok(files)
+}
+
+/// Path of an export file by name, if the name is one of an export file and it exists.
+pub fn export_path(dir: &Path, name: &str) -> Option<(PathBuf, ReportFormat)> {
+    let (_, format, _) = parse_file_name(name)?;
+    let path = dir.join(name);
+
+    path.is_file().then_some((path, format))
+}
+
+/// Removes the oldest files of a house and format beyond the configured number.
+fn rotate(config: &ExportConfig, house: &str, format: ReportFormat) -> Result<(), AppError> {
+    let old = list_exports(&config.dir)?
+        .into_iter()
+        .filter(|file| file.house == house && file.format == format)
+        .skip(config.keep);
+
+    for file in old {
+        std::fs::remove_file(config.dir.join(&file.name))?;
+        log::debug!("removed old report export {0}", file.name);
+    }
+
+    Ok(())
+}
+
+/// Creates a new export file of a house and format stamped with the time.
+///
+/// Files are never overwritten: a name taken within the same millisecond gets
+/// the next free number appended to the stamp.
+pub fn create_file(
+    dir: &Path,
+    house: Uuid,
+    format: ReportFormat,
+    now: DateTime<Utc>,
+) -> Result<(String, File), AppError> {
+    for number in 0u32.. {
+        let suffix = match number {
+            0 => String::new(),
+            n => format!("-{n}"),
+        };
+        let name = format!(
+            "{house}_{0}{suffix}.{1}",
+            now.format(STAMP_FORMAT),
+            format.extension()
+        );
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(&name))
+        {
+            Ok(file) => return Ok((name, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Err(AppError::Internal(format!(
+        "no free export file name for {house}"
+    )))
+}
+
+/// Writes the reports of the houses in the formats and rotates old files.
+///
+/// Houses which no longer exist are skipped.
+pub fn export_reports(
+    conn: &mut SqliteConnection,
+    config: &ExportConfig,
+    houses: &[Uuid],
+    formats: &[ReportFormat],
+    catalog: &Catalog,
+) -> Result<Vec<ExportFile>, AppError> {
+    std::fs::create_dir_all(&config.dir)?;
+
+    let mut files = Vec::new();
+    for &house in houses {
+        let Some(report) = actions::get_house_report(conn, house)? else {
+            continue;
+        };
+
+        let now = Utc::now();
+        for &format in formats {
+            let (name, mut file) = create_file(&config.dir, house, format, now)?;
+            let contents = report_generator::render(&report, format, catalog);
+            file.write_all(contents.as_bytes())?;
+
+            files.push(ExportFile {
+                name,
+                house: house.to_string(),
+                format,
+                created_at: now.timestamp_millis(),
+                size: contents.len() as u64,
+            });
+            rotate(config, &house.to_string(), format)?;
+        }
+    }
+
+    Ok(files)
+}
+
+/// Exports the configured reports on the configured schedule.
+pub fn spawn(pool: DbPool, config: ExportConfig) {
+    let Some(schedule) = config.schedule.clone() else {
+        return;
+    };
+
+    rt::spawn(async move {
+        for next in schedule.upcoming(Utc) {
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            rt::time::sleep(wait).await;
+
+            export_scheduled(pool.clone(), config.clone()).await;
+        }
+    });
+}
+
+async fn export_scheduled(pool: DbPool, config: ExportConfig) {
+    let exported = web::block(move || {
+        let mut conn = pool.get()?;
+
+        let houses = match &config.houses {
+            Some(houses) => houses.clone(),
+            None => actions::live_house_ids(&mut conn)?,
+        };
+        let catalog = Catalog::find(&config.lang).unwrap_or_else(Catalog::default_language);
+        export_reports(&mut conn, &config, &houses, &config.formats, catalog)
+    })
+    .await;
+
+    match exported {
+        Ok(Ok(files)) => log::info!("exported {0} report files", files.len()),
+        Ok(Err(e)) => log::error!("report export failed: {e}"),
+        Err(e) => log::error!("report export failed: {e}"),
+    }
+}
@@ -1,23 +1,447 @@
 use crate::actions;
-use crate::models;
-use crate::report_generator::{generate_list_id, generate_name_id, generate_report_id};
-use actix_web::{error, get, post, web, HttpResponse, Responder};
+use crate::drivers;
+use crate::error::{AppError, ErrorBody};
+use crate::export;
+use crate::live;
+use crate::models::{self, Item};
+use crate::report_generator::{self, generate_list_id, generate_name_id, generate_report_id};
+use crate::udp;
+use actix::Addr;
+use actix_web::http::header::{self, ContentType, ETag, EntityTag, HeaderName, HeaderValue};
+use actix_web::{
+    delete, dev, get, patch, post, put, web, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+    HttpResponseBuilder, Responder,
+};
+use actix_web_actors::ws;
 use diesel::{prelude::*, r2d2};
+use std::sync::Arc;
+use utoipa::OpenApi;
 use uuid::Uuid;
 /// Short-hand for the database pool type to use throughout the app.
 type DbPool = r2d2::Pool<r2d2::ConnectionManager<SqliteConnection>>;
 
-/// Get device report.
+/// Marks a response of a legacy GET route which is scheduled for removal.
+fn deprecated(mut response: HttpResponse) -> HttpResponse {
+    response.headers_mut().insert(
+        HeaderName::from_static("deprecation"),
+        HeaderValue::from_static("true"),
+    );
+    response
+}
+
+/// Entity tag of a device, derived from its version.
+fn device_etag(device: &models::Device) -> EntityTag {
+    EntityTag::new_strong(device.version.to_string())
+}
+
+/// Device versions listed by the `If-Match` header.
+///
+/// Returns `None` when the header is missing or `*`; tags which aren't strong
+/// device etags are refused rather than ignored.
+fn if_match_versions(req: &HttpRequest) -> Result<Option<Vec<i32>>, AppError> {
+    let invalid = || {
+        AppError::Validation(String::from(
+            "If-Match must list device versions as strong entity tags",
+        ))
+    };
+
+    let mut items = Vec::new();
+    for value in req.headers().get_all(header::IF_MATCH) {
+        let value = value.to_str().map_err(|_| invalid())?;
+        items.extend(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty()),
+        );
+    }
+    match items.as_slice() {
+        [] if req.headers().contains_key(header::IF_MATCH) => Err(invalid()),
+        [] | ["*"] => Ok(None),
+        items => items
+            .iter()
+            .map(|item| {
+                let tag = item.parse::<EntityTag>().ok().filter(|tag| !tag.weak)?;
+                tag.tag().parse::<i32>().ok()
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(Some)
+            .ok_or_else(invalid),
+    }
+}
+
+/// Header naming the client in the audit log.
+const CLIENT_ID: &str = "x-client-id";
+/// Longest client identity kept in the audit log, in characters.
+const CLIENT_ID_MAX_LEN: usize = 128;
+/// Header of a reconnecting event stream client naming the last event it got.
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// Client making a request, recorded in the audit log.
+///
+/// Identified by the `X-Client-Id` header, or else by its address. The actor
+/// is kept in the request extensions so that the changes it collects can be
+/// published once the response is ready.
+pub struct Client(Arc<models::Actor>);
+
+impl std::ops::Deref for Client {
+    type Target = models::Actor;
+
+    fn deref(&self) -> &models::Actor {
+        &self.0
+    }
+}
+
+impl FromRequest for Client {
+    type Error = AppError;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut dev::Payload) -> Self::Future {
+        let named = req
+            .headers()
+            .get(CLIENT_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|name| !name.is_empty());
+        let identity = match named {
+            Some(name) => name.chars().take(CLIENT_ID_MAX_LEN).collect(),
+            None => req
+                .peer_addr()
+                .map_or_else(|| String::from("unknown"), |addr| addr.ip().to_string()),
+        };
+
+        let actor = Arc::new(models::Actor::new(identity));
+        req.extensions_mut().insert(Arc::clone(&actor));
+
+        std::future::ready(Ok(Client(actor)))
+    }
+}
+
+/// Starts a 200 response for one page of a list.
+///
+/// When there are more items the next page is linked through the `Link` and
+/// `X-Next-Cursor` headers.
+fn page_response(req: &HttpRequest, next_cursor: Option<models::Cursor>) -> HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+
+    if let Some(cursor) = next_cursor {
+        let cursor_pair = format!("cursor={cursor}");
+        let mut query: Vec<&str> = req
+            .query_string()
+            .split('&')
+            .filter(|pair| !pair.is_empty() && !pair.starts_with("cursor="))
+            .collect();
+        query.push(&cursor_pair);
+
+        response.insert_header((
+            header::LINK,
+            format!("<{0}?{1}>; rel=\"next\"", req.path(), query.join("&")),
+        ));
+        response.insert_header(("X-Next-Cursor", cursor.to_string()));
+    }
+
+    response
+}
+
+/// Rejects blank names of houses, rooms and devices.
+fn validate_name(name: &str) -> Result<(), AppError> {
+    if name.trim().is_empty() {
+        return Err(AppError::Validation(String::from("Name must not be empty")));
+    }
+    Ok(())
+}
+
+/// Registers the deprecated GET routes which remove entities or toggle a device.
+///
+/// They are only mounted while `LEGACY_GET_ROUTES` is enabled and will be
+/// dropped in the next release.
+pub fn configure_legacy_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(rem_house)
+        .service(rem_room)
+        .service(rem_device)
+        .service(change_state_device);
+}
+
+/// OpenAPI description of the routes under `/api/v1`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Smart house API"),
+    servers((url = "/api/v1")),
+    paths(
+        list_houses,
+        add_house,
+        get_house,
+        patch_house,
+        rename_house,
+        delete_house,
+        list_house_rooms,
+        get_house_tree,
+        get_devices_report,
+        get_house_energy,
+        create_export,
+        get_exports,
+        get_export,
+        list_rooms,
+        add_room,
+        get_room,
+        patch_room,
+        rename_room,
+        move_room,
+        delete_room,
+        list_room_devices,
+        list_devices,
+        add_device,
+        get_device_kinds,
+        get_device,
+        get_device_var,
+        get_device_health,
+        patch_device,
+        rename_device,
+        move_device,
+        put_device_state,
+        delete_device,
+        get_device_readings,
+        add_device_readings,
+        get_device_history,
+        get_trash,
+        list_events,
+        get_event_stream,
+        get_event,
+        get_ws,
+        get_metrics,
+        restore_house,
+        restore_room,
+        restore_device,
+    ),
+    tags(
+        (name = "houses"),
+        (name = "rooms"),
+        (name = "devices"),
+        (name = "readings"),
+        (name = "reports"),
+        (name = "trash", description = "Deleted entities which can still be restored"),
+        (name = "events", description = "Audit log of changes to houses, rooms and devices"),
+        (name = "live", description = "Changes pushed to subscribers as they happen"),
+        (name = "metrics", description = "Counts of what devices sent to the server"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the routes of the current API version; mounted under `/api/v1`.
+pub fn configure_api(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_houses)
+        .service(add_house)
+        .service(get_house)
+        .service(patch_house)
+        .service(rename_house)
+        .service(delete_house)
+        .service(restore_house)
+        .service(list_house_rooms)
+        .service(get_house_tree)
+        .service(get_devices_report)
+        .service(get_house_energy)
+        .service(create_export)
+        .service(get_exports)
+        .service(get_export)
+        .service(list_rooms)
+        .service(add_room)
+        .service(get_room)
+        .service(patch_room)
+        .service(rename_room)
+        .service(move_room)
+        .service(delete_room)
+        .service(restore_room)
+        .service(list_room_devices)
+        .service(list_devices)
+        .service(add_device)
+        .service(get_device_kinds)
+        .service(get_device)
+        .service(get_device_var)
+        .service(get_device_health)
+        .service(patch_device)
+        .service(rename_device)
+        .service(move_device)
+        .service(put_device_state)
+        .service(delete_device)
+        .service(restore_device)
+        .service(get_device_readings)
+        .service(add_device_readings)
+        .service(get_device_history)
+        .service(get_trash)
+        .service(list_events)
+        // before `/events/{event_id}`, which would take `stream` for an id
+        .service(get_event_stream)
+        .service(get_event)
+        .service(get_ws)
+        .service(get_metrics)
+        .service(get_openapi)
+        .service(get_swagger_ui)
+        .service(get_redoc);
+}
+
+/// Registers the routes served at the root path before the API was versioned.
+///
+/// They answer with a `Deprecation` header; clients should move to `/api/v1`.
+pub fn configure_unversioned_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_device)
+        .service(add_device)
+        .service(get_room)
+        .service(add_room)
+        .service(get_house)
+        .service(add_house)
+        .service(delete_house)
+        .service(delete_room)
+        .service(delete_device)
+        .service(patch_house)
+        .service(patch_room)
+        .service(patch_device)
+        .service(rename_house)
+        .service(rename_room)
+        .service(rename_device)
+        .service(move_room)
+        .service(move_device)
+        .service(get_devices_report)
+        .service(get_list_houses)
+        .service(get_list_rooms)
+        .service(get_list_devices)
+        .service(put_device_state)
+        .service(get_devices_list)
+        .service(get_device_var)
+        .service(get_rooms_list)
+        .service(get_device_kinds)
+        .service(get_device_readings)
+        .service(add_device_readings)
+        .service(get_device_history)
+        .service(get_trash)
+        .service(restore_house)
+        .service(restore_room)
+        .service(restore_device);
+}
+
+/// Serves the OpenAPI document of the API.
+#[get("/openapi.json")]
+async fn get_openapi() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Serves Swagger UI for exploring the API.
+#[get("/docs")]
+async fn get_swagger_ui() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("docs/swagger.html"))
+}
+
+/// Serves the API reference rendered by Redoc.
+#[get("/redoc")]
+async fn get_redoc() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(include_str!("docs/redoc.html"))
+}
+
+/// Get a house with its rooms and their devices.
 ///
 /// Extracts:
 /// - the database pool handle from application data
-/// - a user UID from the request path
+/// - a house UID from the request path
+#[utoipa::path(
+    tag = "houses",
+    responses(
+        (status = 200, description = "OK", body = models::HouseTree),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/house/{house_uid}/tree")]
+async fn get_house_tree(
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let tree = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::get_house_tree(&mut conn, house_uid)
+    })
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
+
+    // house was found; return 200 response with the JSON formatted tree
+    Ok(HttpResponse::Ok().json(tree))
+}
+
+/// Picks the representation of a report from `?format=` or else the `Accept` header.
+fn report_format(req: &HttpRequest, format: Option<models::ReportFormat>) -> models::ReportFormat {
+    if let Some(format) = format {
+        return format;
+    }
+
+    req.get_header::<header::Accept>()
+        .and_then(|accept| {
+            accept
+                .ranked()
+                .iter()
+                .find_map(|mime| models::ReportFormat::from_media_type(mime.essence_str()))
+        })
+        .unwrap_or(models::ReportFormat::Json)
+}
+
+/// Picks the language of report text from `?lang=` or else the `Accept-Language` header.
+fn report_catalog(
+    req: &HttpRequest,
+    lang: Option<&str>,
+) -> Result<&'static report_generator::Catalog, AppError> {
+    if let Some(lang) = lang {
+        return Ok(report_generator::Catalog::get(lang)?);
+    }
+
+    let ranked = req
+        .get_header::<header::AcceptLanguage>()
+        .map(|accept| accept.ranked())
+        .unwrap_or_default();
+    Ok(report_generator::Catalog::negotiate(
+        ranked
+            .iter()
+            .filter_map(|preference| preference.item())
+            .map(|tag| tag.primary_language()),
+    ))
+}
+
+/// Get house report.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a house UID from the request path
+/// - the report format from the query string or the `Accept` header
+/// - the report language from the query string or the `Accept-Language` header
+#[utoipa::path(
+    tag = "reports",
+    params(models::ReportQuery),
+    responses(
+        (status = 200, description = "OK", content(
+            (models::HouseReport = "application/json"),
+            (String = "text/plain"),
+            (String = "text/markdown"),
+            (String = "text/csv"),
+            (String = "text/html"),
+        )),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
 #[get("/report/{house_uid}")]
 pub async fn get_devices_report(
+    req: HttpRequest,
     pool: web::Data<DbPool>,
     house_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+    query: web::Query<models::ReportQuery>,
+) -> Result<impl Responder, AppError> {
     let house_uid = house_uid.into_inner();
+    let format = report_format(&req, query.format);
+    let catalog = report_catalog(&req, query.lang.as_deref())?;
 
     // use web::block to offload blocking Diesel queries without blocking server thread
     let report = web::block(move || {
@@ -26,19 +450,85 @@ pub async fn get_devices_report(
 
         actions::get_house_report(&mut conn, house_uid)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError);
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
+
+    // house was found; return 200 response with the report in the requested format
+    Ok(HttpResponse::Ok()
+        .insert_header((header::VARY, "Accept, Accept-Language"))
+        .insert_header((header::CONTENT_LANGUAGE, catalog.lang()))
+        .content_type(format.content_type())
+        .body(report_generator::render(&report, format, catalog)))
+}
 
-    Ok(match report {
-        // house was found; return 200 response with JSON formatted user object
-        Ok(report) => HttpResponse::Ok().json(report),
+/// Time range of an energy report when the query names none, in seconds.
+const ENERGY_DEFAULT_RANGE: i64 = 24 * 60 * 60;
 
-        // House was not found; return 404 response with error message
-        Err(e) => HttpResponse::NotFound().body(format!(
-            "No report found for house with UID: {house_uid} and error {e}"
+/// Get energy report of the sockets in a house.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a house UID from the request path
+/// - the time range from the query string, the last day by default
+/// - the report format from the query string or the `Accept` header
+/// - the report language from the query string or the `Accept-Language` header
+#[utoipa::path(
+    tag = "reports",
+    params(models::EnergyQuery),
+    responses(
+        (status = 200, description = "OK", content(
+            (models::EnergyReport = "application/json"),
+            (String = "text/plain"),
+            (String = "text/markdown"),
+            (String = "text/csv"),
+            (String = "text/html"),
         )),
+        (status = 400, description = "Invalid time range", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/house/{house_uid}/energy")]
+async fn get_house_energy(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+    query: web::Query<models::EnergyQuery>,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+    let format = report_format(&req, query.format);
+    let catalog = report_catalog(&req, query.lang.as_deref())?;
+
+    let to = query.to.unwrap_or_else(actions::unix_now);
+    let from = match query.from {
+        Some(from) => from,
+        None => to
+            .checked_sub(ENERGY_DEFAULT_RANGE)
+            .ok_or_else(range_out_of_bounds)?,
+    };
+    if from >= to {
+        return Err(AppError::Validation(String::from(
+            "`from` must be before `to`",
+        )));
+    }
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let report = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::get_house_energy(&mut conn, house_uid, from, to)
     })
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
+
+    // house was found; return 200 response with the report in the requested format
+    Ok(HttpResponse::Ok()
+        .insert_header((header::VARY, "Accept, Accept-Language"))
+        .insert_header((header::CONTENT_LANGUAGE, catalog.lang()))
+        .content_type(format.content_type())
+        .body(report_generator::render_energy(&report, format, catalog)))
 }
 
 /// Get devices in room.
@@ -46,37 +536,27 @@ pub async fn get_devices_report(
 /// Extracts:
 /// - the database pool handle from application data
 /// - a user UID from the request path
+/// - paging, filters and sort order from the query string
 #[get("/room/{room_uid}/list")]
 async fn get_list_devices(
+    req: HttpRequest,
     pool: web::Data<DbPool>,
     room_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+    query: web::Query<models::ListQuery>,
+) -> Result<impl Responder, AppError> {
     let room_uid = room_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let devices = web::block(move || {
+    let page = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::list_device_in_room(&mut conn, room_uid)
+        actions::list_device_in_room(&mut conn, room_uid, &query)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError);
+    .await??;
 
-    Ok(match devices {
-        // house was found; return 200 response with JSON formatted user object
-        Ok(devices) => {
-            let res = generate_list_id(devices);
-            match res {
-                Ok(data) => HttpResponse::Ok().json(data),
-                Err(e) => HttpResponse::BadRequest().json(e.to_string()),
-            }
-        }
-
-        // House was not found; return 404 response with error message
-        Err(e) => HttpResponse::NotFound().body(format!("No devices found with error {e}")),
-    })
+    // devices were found; return 200 response with JSON formatted list of ids
+    Ok(page_response(&req, page.next_cursor).json(generate_list_id(page.items)?))
 }
 
 /// Get devices in room.
@@ -84,126 +564,268 @@ async fn get_list_devices(
 /// Extracts:
 /// - the database pool handle from application data
 /// - a user UID from the request path
+/// - paging, filters and sort order from the query string
 #[get("/house/{house_uid}/list")]
 async fn get_list_rooms(
+    req: HttpRequest,
     pool: web::Data<DbPool>,
     house_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+    query: web::Query<models::ListQuery>,
+) -> Result<impl Responder, AppError> {
     let house_uid = house_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let rooms = web::block(move || {
+    let page = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::list_room_by_id(&mut conn, house_uid)
+        actions::list_room_by_id(&mut conn, house_uid, &query)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError);
+    .await??;
 
-    Ok(match rooms {
-        // house was found; return 200 response with JSON formatted user object
-        Ok(rooms) => {
-            let res = generate_name_id(rooms);
-            match res {
-                Ok(data) => HttpResponse::Ok().json(data),
-                Err(e) => HttpResponse::BadRequest().json(e.to_string()),
-            }
-        }
-
-        // House was not found; return 404 response with error message
-        Err(e) => HttpResponse::NotFound().body(format!("No rooms found with error {e}")),
-    })
+    // rooms were found; return 200 response with JSON formatted names and ids
+    Ok(page_response(&req, page.next_cursor).json(generate_name_id(page.items)?))
 }
 
 /// Get houses.
 ///
 /// Extracts:
 /// - the database pool handle from application data
-/// - a user UID from the request path
+/// - paging, filters and sort order from the query string
 #[get("/house-list")]
-async fn get_list_houses(pool: web::Data<DbPool>) -> actix_web::Result<impl Responder> {
+async fn get_list_houses(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<models::ListQuery>,
+) -> Result<impl Responder, AppError> {
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let houses = web::block(move || {
+    let page = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::list_houses(&mut conn)
+        actions::list_houses(&mut conn, &query)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError);
+    .await??;
 
-    Ok(match houses {
-        // house was found; return 200 response with JSON formatted user object
-        Ok(houses) => {
-            let res = generate_list_id(houses);
-            match res {
-                Ok(data) => HttpResponse::Ok().json(data),
-                Err(e) => HttpResponse::BadRequest().json(e.to_string()),
-            }
-        }
+    // houses were found; return 200 response with JSON formatted list of ids
+    Ok(page_response(&req, page.next_cursor).json(generate_list_id(page.items)?))
+}
+
+#[get("/devices-list")] //todo
+async fn get_devices_list(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<models::ListQuery>,
+) -> Result<impl Responder, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let page = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
 
-        // House was not found; return 404 response with error message
-        Err(e) => HttpResponse::NotFound().body(format!("No houses found with error {e}")),
+        actions::get_devices_list(&mut conn, &query)
     })
+    .await??;
+
+    // devices were found; return 200 response with JSON formatted list of ids
+    Ok(page_response(&req, page.next_cursor).json(generate_list_id(page.items)?))
 }
 
-#[get("/devices-list")] //todo
-async fn get_devices_list(pool: web::Data<DbPool>) -> actix_web::Result<impl Responder> {
+#[get("/rooms-list")] //todo
+async fn get_rooms_list(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<models::ListQuery>,
+) -> Result<impl Responder, AppError> {
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let devices = web::block(move || {
+    let page = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::get_devices_list(&mut conn)
+        actions::get_rooms_list(&mut conn, &query)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError);
+    .await??;
+    let catalog = report_catalog(&req, None)?;
 
-    Ok(match devices {
-        // house was found; return 200 response with JSON formatted user object
-        Ok(devices) => {
-            let res = generate_list_id(devices);
-            match res {
-                Ok(data) => HttpResponse::Ok().json(data),
-                Err(e) => HttpResponse::BadRequest().json(e.to_string()),
-            }
-        }
+    // rooms were found; return 200 response with JSON formatted list of ids
+    Ok(page_response(&req, page.next_cursor).json(generate_report_id(page.items, catalog)?))
+}
+
+/// List devices.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - paging, filters and sort order from the query string
+/// - the fields to return from the query string
+#[utoipa::path(
+    tag = "devices",
+    params(models::ListQuery, models::Fields),
+    responses(
+        (status = 200, description = "OK", body = Vec<models::Device>),
+        (status = 400, description = "Invalid request", body = ErrorBody)
+    )
+)]
+#[get("/devices")]
+async fn list_devices(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<models::ListQuery>,
+    fields: web::Query<models::Fields>,
+) -> Result<impl Responder, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let page = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
 
-        // House was not found; return 404 response with error message
-        Err(e) => HttpResponse::NotFound().body(format!("No devices found with error {e}")),
+        actions::get_devices_list(&mut conn, &query)
     })
+    .await??;
+
+    // return 200 response with JSON formatted devices, possibly none
+    Ok(page_response(&req, page.next_cursor).json(fields.project(page.items)?))
 }
 
-#[get("/rooms-list")] //todo
-async fn get_rooms_list(pool: web::Data<DbPool>) -> actix_web::Result<impl Responder> {
+/// List rooms.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - paging, filters and sort order from the query string
+/// - the fields to return from the query string
+#[utoipa::path(
+    tag = "rooms",
+    params(models::ListQuery, models::Fields),
+    responses(
+        (status = 200, description = "OK", body = Vec<models::Room>),
+        (status = 400, description = "Invalid request", body = ErrorBody)
+    )
+)]
+#[get("/rooms")]
+async fn list_rooms(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<models::ListQuery>,
+    fields: web::Query<models::Fields>,
+) -> Result<impl Responder, AppError> {
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let devices = web::block(move || {
+    let page = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::get_rooms_list(&mut conn)
+        actions::get_rooms_list(&mut conn, &query)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError);
+    .await??;
 
-    Ok(match devices {
-        // house was found; return 200 response with JSON formatted user object
-        Ok(devices) => {
-            let res = generate_report_id(devices);
-            match res {
-                Ok(data) => HttpResponse::Ok().json(data),
-                Err(e) => HttpResponse::BadRequest().json(e.to_string()),
-            }
-        }
+    // return 200 response with JSON formatted rooms, possibly none
+    Ok(page_response(&req, page.next_cursor).json(fields.project(page.items)?))
+}
+
+/// List houses.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - paging, filters and sort order from the query string
+/// - the fields to return from the query string
+#[utoipa::path(
+    tag = "houses",
+    params(models::ListQuery, models::Fields),
+    responses(
+        (status = 200, description = "OK", body = Vec<models::House>),
+        (status = 400, description = "Invalid request", body = ErrorBody)
+    )
+)]
+#[get("/houses")]
+async fn list_houses(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<models::ListQuery>,
+    fields: web::Query<models::Fields>,
+) -> Result<impl Responder, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let page = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::list_houses(&mut conn, &query)
+    })
+    .await??;
+
+    // return 200 response with JSON formatted houses, possibly none
+    Ok(page_response(&req, page.next_cursor).json(fields.project(page.items)?))
+}
+
+/// List devices in a room.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a room UID from the request path
+/// - paging, filters and sort order from the query string
+/// - the fields to return from the query string
+#[utoipa::path(
+    tag = "rooms",
+    params(models::ListQuery, models::Fields),
+    responses(
+        (status = 200, description = "OK", body = Vec<models::Device>),
+        (status = 400, description = "Invalid request", body = ErrorBody)
+    )
+)]
+#[get("/room/{room_uid}/devices")]
+async fn list_room_devices(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    room_uid: web::Path<Uuid>,
+    query: web::Query<models::ListQuery>,
+    fields: web::Query<models::Fields>,
+) -> Result<impl Responder, AppError> {
+    let room_uid = room_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let page = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::list_device_in_room(&mut conn, room_uid, &query)
+    })
+    .await??;
+
+    // return 200 response with JSON formatted devices, possibly none
+    Ok(page_response(&req, page.next_cursor).json(fields.project(page.items)?))
+}
+
+/// List rooms in a house.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a house UID from the request path
+/// - paging, filters and sort order from the query string
+/// - the fields to return from the query string
+#[utoipa::path(
+    tag = "houses",
+    params(models::ListQuery, models::Fields),
+    responses(
+        (status = 200, description = "OK", body = Vec<models::Room>),
+        (status = 400, description = "Invalid request", body = ErrorBody)
+    )
+)]
+#[get("/house/{house_uid}/rooms")]
+async fn list_house_rooms(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+    query: web::Query<models::ListQuery>,
+    fields: web::Query<models::Fields>,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let page = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
 
-        // House was not found; return 404 response with error message
-        Err(e) => HttpResponse::NotFound().body(format!("No rooms found with error {e}")),
+        actions::list_room_by_id(&mut conn, house_uid, &query)
     })
+    .await??;
+
+    // return 200 response with JSON formatted rooms, possibly none
+    Ok(page_response(&req, page.next_cursor).json(fields.project(page.items)?))
 }
 
 /// Finds user by UID.
@@ -211,11 +833,18 @@ async fn get_rooms_list(pool: web::Data<DbPool>) -> actix_web::Result<impl Respo
 /// Extracts:
 /// - the database pool handle from application data
 /// - a user UID from the request path
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = models::Device),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
 #[get("/device/{device_uid}")]
 async fn get_device(
     pool: web::Data<DbPool>,
     device_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+) -> Result<impl Responder, AppError> {
     let device_uid = device_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
@@ -225,24 +854,68 @@ async fn get_device(
 
         actions::find_device_by_id(&mut conn, device_uid)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was found; return 200 response with JSON formatted device object
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(device_etag(&device)))
+        .json(device))
+}
+
+/// Returns the legacy `variable` of a device.
+///
+/// Kept as a compatibility view: the primary reading of the device kind is
+/// mirrored into `variable` whenever it is recorded.
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = i32),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/device/{device_uid}/var")]
+async fn get_device_var(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
 
-    Ok(match device {
-        // user was found; return 200 response with JSON formatted user object
-        Some(device) => HttpResponse::Ok().json(device),
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let device = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
 
-        // user was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {device_uid}")),
+        actions::find_device_by_id(&mut conn, device_uid)
     })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was found; return 200 response with JSON formatted variable
+    Ok(HttpResponse::Ok().json(device.variable))
 }
 
-#[get("/device/{device_uid}/var")]
-async fn get_device_var(
+/// Checks whether the hardware behind a device answers.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - the device drivers from application data
+/// - a device UID from the request path
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = models::DeviceHealth),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/device/{device_uid}/health")]
+async fn get_device_health(
     pool: web::Data<DbPool>,
+    drivers: web::Data<drivers::Drivers>,
     device_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+) -> Result<impl Responder, AppError> {
     let device_uid = device_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
@@ -252,24 +925,174 @@ async fn get_device_var(
 
         actions::find_device_by_id(&mut conn, device_uid)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // the check waits for the device to answer
+    let health = web::block(move || drivers.health(&device)).await?;
+
+    // device was found; return 200 response with JSON formatted health
+    Ok(HttpResponse::Ok().json(health))
+}
+
+/// Lists the latest readings of a device.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a device UID from the request path
+#[utoipa::path(
+    tag = "readings",
+    responses(
+        (status = 200, description = "OK", body = Vec<models::Reading>),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/device/{device_uid}/readings")]
+async fn get_device_readings(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let readings = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        match actions::find_device_by_id(&mut conn, device_uid)? {
+            Some(_) => actions::list_readings(&mut conn, device_uid).map(Some),
+            None => Ok(None),
+        }
+    })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was found; return 200 response with JSON formatted readings
+    Ok(HttpResponse::Ok().json(readings))
+}
+
+/// Stores new readings of a device.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a device UID from the request path
+/// - a JSON array of readings from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "readings",
+    responses(
+        (status = 200, description = "OK", body = Vec<models::Reading>),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[post("/device/{device_uid}/readings")]
+async fn add_device_readings(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+    form: web::Json<Vec<models::NewReading>>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let readings = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::record_readings(&mut conn, device_uid, &form, &client)
+    })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // readings were stored; return 200 response with JSON formatted readings
+    Ok(HttpResponse::Ok().json(readings))
+}
+
+/// Default length of a history query, in seconds.
+const HISTORY_DEFAULT_RANGE: i64 = 24 * 60 * 60;
+/// Upper bound on the number of buckets a history query may return.
+const HISTORY_MAX_POINTS: i64 = 10_000;
+/// Number of buckets used when no step is requested.
+const HISTORY_DEFAULT_POINTS: i64 = 100;
+
+/// Error for a time range whose bounds or length don't fit in a timestamp.
+fn range_out_of_bounds() -> AppError {
+    AppError::Validation(String::from("Time range is out of bounds"))
+}
+
+/// Returns downsampled history of a device metric.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a device UID from the request path
+/// - metric, time range and bucket size from the query string
+#[utoipa::path(
+    tag = "readings",
+    params(models::HistoryQuery),
+    responses(
+        (status = 200, description = "OK", body = models::History),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/device/{device_uid}/history")]
+async fn get_device_history(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+    query: web::Query<models::HistoryQuery>,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+    let query = query.into_inner();
+
+    let to = query.to.unwrap_or_else(|| actions::unix_now() + 1);
+    let from = match query.from {
+        Some(from) => from,
+        None => to
+            .checked_sub(HISTORY_DEFAULT_RANGE)
+            .ok_or_else(range_out_of_bounds)?,
+    };
+    if from >= to {
+        return Err(AppError::Validation(String::from(
+            "`from` must be before `to`",
+        )));
+    }
+    let span = to.checked_sub(from).ok_or_else(range_out_of_bounds)?;
+    let step = query.step.unwrap_or((span / HISTORY_DEFAULT_POINTS).max(1));
+    if step <= 0 || span / step > HISTORY_MAX_POINTS {
+        return Err(AppError::Validation(format!(
+            "`step` must be positive and yield at most {HISTORY_MAX_POINTS} buckets"
+        )));
+    }
 
-    Ok(match device {
-        // user was found; return 200 response with JSON formatted user object
-        Some(device) => HttpResponse::Ok().json(device.variable),
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let history = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
 
-        // user was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {device_uid}")),
+        actions::get_device_history(&mut conn, device_uid, query.metric, from, to, step)
     })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was found; return 200 response with JSON formatted history
+    Ok(HttpResponse::Ok().json(history))
 }
 
+/// Toggles the state of a device.
+///
+/// Deprecated: use `PUT /device/{device_uid}/state` with an explicit state instead.
 #[get("/device/{device_uid}/state")]
 async fn change_state_device(
     pool: web::Data<DbPool>,
     device_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+    drivers: web::Data<drivers::Drivers>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
     let device_uid = device_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
@@ -277,26 +1100,21 @@ async fn change_state_device(
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::update_state_device(&mut conn, device_uid)
+        actions::update_state_device(&mut conn, device_uid, &drivers, &client)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
 
-    Ok(match device {
-        // user was found; return 200 response with JSON formatted user object
-        Some(device) => HttpResponse::Ok().json(device),
-
-        // user was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {device_uid}")),
-    })
+    // device was toggled; return 200 response with JSON formatted device object
+    Ok(deprecated(HttpResponse::Ok().json(device)))
 }
 
 #[post("/device/{device_uid}")]
 async fn post_device(
     pool: web::Data<DbPool>,
     device_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+) -> Result<impl Responder, AppError> {
     let device_uid = device_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
@@ -306,176 +1124,1171 @@ async fn post_device(
 
         actions::find_device_by_id(&mut conn, device_uid)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
 
-    Ok(match device {
-        // user was found; return 200 response with JSON formatted user object
-        Some(device) => HttpResponse::Ok().json(device),
-
-        // user was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {device_uid}")),
-    })
+    // device was found; return 200 response with JSON formatted device object
+    Ok(HttpResponse::Ok().json(device))
 }
 
 /// Remove device by UID.
 ///
+/// Deprecated: use `DELETE /device/{device_uid}` instead.
+///
 /// Extracts:
 /// - the database pool handle from application data
 /// - a user UID from the request path
+/// - the identity of the client from the `X-Client-Id` header or its address
 #[get("/device/{device_uid}/remove")]
 async fn rem_device(
     pool: web::Data<DbPool>,
     device_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+    client: Client,
+) -> Result<impl Responder, AppError> {
     let device_uid = device_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let device = web::block(move || {
+    let removal = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::remove_device_by_id(&mut conn, device_uid)
+        actions::remove_device_by_id(
+            &mut conn,
+            device_uid,
+            &models::DeleteOptions::default(),
+            &client,
+        )
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
 
-    Ok(match device {
-        // user was found; return 200 response with JSON formatted user object
-        Some(device) => HttpResponse::Ok().json(device),
-
-        // user was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {device_uid}")),
-    })
+    // device was removed; return 200 response with JSON formatted device object
+    Ok(deprecated(HttpResponse::Ok().json(removal.tree)))
 }
 
 /// Remove room by UID.
 ///
+/// Deprecated: use `DELETE /room/{room_uid}` instead.
+///
 /// Extracts:
 /// - the database pool handle from application data
 /// - a user UID from the request path
+/// - the identity of the client from the `X-Client-Id` header or its address
 #[get("/room/{room_uid}/remove")]
 async fn rem_room(
     pool: web::Data<DbPool>,
     room_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+    client: Client,
+) -> Result<impl Responder, AppError> {
     let room_uid = room_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let room = web::block(move || {
+    let removal = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::remove_room_by_id(&mut conn, room_uid)
+        actions::remove_room_by_id(
+            &mut conn,
+            room_uid,
+            &models::DeleteOptions::default(),
+            &client,
+        )
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // room was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("room", room_uid))?;
 
-    Ok(match room {
-        // user was found; return 200 response with JSON formatted user object
-        Some(room) => HttpResponse::Ok().json(room),
-
-        // user was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {room_uid}")),
-    })
+    // room was removed; return 200 response with JSON formatted room object
+    Ok(deprecated(HttpResponse::Ok().json(removal.tree.room)))
 }
 
 /// Remove house by UID.
 ///
+/// Deprecated: use `DELETE /house/{house_uid}` instead.
+///
 /// Extracts:
 /// - the database pool handle from application data
 /// - a user UID from the request path
+/// - the identity of the client from the `X-Client-Id` header or its address
 #[get("/house/{house_uid}/remove")]
 async fn rem_house(
     pool: web::Data<DbPool>,
     house_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
+    client: Client,
+) -> Result<impl Responder, AppError> {
     let house_uid = house_uid.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let house = web::block(move || {
+    let removal = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::remove_house_by_id(&mut conn, house_uid)
+        actions::remove_house_by_id(
+            &mut conn,
+            house_uid,
+            &models::DeleteOptions::default(),
+            &client,
+        )
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
 
-    Ok(match house {
-        // user was found; return 200 response with JSON formatted user object
-        Some(house) => HttpResponse::Ok().json(house),
-
-        // user was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {house_uid}")),
-    })
+    // house was removed; return 200 response with JSON formatted house object
+    Ok(deprecated(HttpResponse::Ok().json(removal.tree.house)))
 }
 
-/// Finds room by UID.
+/// Deletes device by UID.
+///
+/// Responds with the device and the number of readings removed with it;
+/// `?dry_run=true` only reports them.
 ///
 /// Extracts:
 /// - the database pool handle from application data
-/// - a user UID from the request path
-#[get("/room/{room_uid}")]
-async fn get_room(
+/// - a device UID from the request path
+/// - the dry run option from the query string
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "devices",
+    params(models::DeleteOptions),
+    responses(
+        (status = 200, description = "OK", body = models::Removal<models::Device>),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[delete("/device/{device_uid}")]
+async fn delete_device(
     pool: web::Data<DbPool>,
-    room_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
-    let room_uid = room_uid.into_inner();
+    device_uid: web::Path<Uuid>,
+    options: web::Query<models::DeleteOptions>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+    let options = options.into_inner();
 
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let room = web::block(move || {
+    let removal = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::find_room_by_id(&mut conn, room_uid)
+        actions::remove_device_by_id(&mut conn, device_uid, &options, &client)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
-
-    Ok(match room {
-        // room was found; return 200 response with JSON formatted user object
-        Some(room) => HttpResponse::Ok().json(room),
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
 
-        // room was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {room_uid}")),
-    })
+    // device was removed; return 200 response with JSON formatted device and counts
+    Ok(HttpResponse::Ok().json(removal))
 }
 
-/// Finds house by UID.
+/// Partially updates device by UID.
 ///
 /// Extracts:
 /// - the database pool handle from application data
-/// - a user UID from the request path
-#[get("/house/{house_uid}")]
-async fn get_house(
+/// - a device UID from the request path
+/// - a JSON form containing the fields to change from the request body
+/// - the device drivers from application data
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = models::Device),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody),
+        (status = 502, description = "Device unavailable", body = ErrorBody)
+    )
+)]
+#[patch("/device/{device_uid}")]
+async fn patch_device(
     pool: web::Data<DbPool>,
-    house_uid: web::Path<Uuid>,
-) -> actix_web::Result<impl Responder> {
-    let house_uid = house_uid.into_inner();
+    device_uid: web::Path<Uuid>,
+    form: web::Json<models::DevicePatch>,
+    drivers: web::Data<drivers::Drivers>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+    if let Some(name) = &form.name {
+        validate_name(name)?;
+    }
 
     // use web::block to offload blocking Diesel queries without blocking server thread
-    let house = web::block(move || {
+    let device = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::update_device(&mut conn, device_uid, &form, &drivers, &client)
+    })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was updated; return 200 response with JSON formatted device object
+    Ok(HttpResponse::Ok().json(device))
+}
+
+/// Renames device by UID.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a device UID from the request path
+/// - a JSON form containing the new name from the request body
+/// - the device drivers from application data
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = models::Device),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[put("/device/{device_uid}/name")]
+async fn rename_device(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+    form: web::Json<models::Rename>,
+    drivers: web::Data<drivers::Drivers>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+    validate_name(&form.name)?;
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let device = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        let patch = models::DevicePatch {
+            name: Some(form.into_inner().name),
+            ..Default::default()
+        };
+
+        actions::update_device(&mut conn, device_uid, &patch, &drivers, &client)
+    })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was renamed; return 200 response with JSON formatted device object
+    Ok(HttpResponse::Ok().json(device))
+}
+
+/// Moves device to another room.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a device UID from the request path
+/// - a JSON form containing the target room UID from the request body
+/// - the device drivers from application data
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = models::Device),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[put("/device/{device_uid}/room")]
+async fn move_device(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+    form: web::Json<models::MoveDevice>,
+    drivers: web::Data<drivers::Drivers>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let device = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        let patch = models::DevicePatch {
+            room: Some(form.into_inner().room),
+            ..Default::default()
+        };
+
+        actions::update_device(&mut conn, device_uid, &patch, &drivers, &client)
+    })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was moved; return 200 response with JSON formatted device object
+    Ok(HttpResponse::Ok().json(device))
+}
+
+/// Sets the state of a device.
+///
+/// The change is only applied if the device is at the version given by the
+/// `If-Match` header or the `version` field, when either is present; both have
+/// to agree when both are given. The response reports whether the state
+/// actually changed.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a device UID from the request path
+/// - an optional `If-Match` header with the expected device version
+/// - a JSON form containing the target state from the request body
+/// - the device drivers from application data
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = models::StateChange),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Changed while being switched", body = ErrorBody),
+        (status = 412, description = "Version mismatch", body = ErrorBody),
+        (status = 502, description = "Device unavailable", body = ErrorBody)
+    )
+)]
+#[put("/device/{device_uid}/state")]
+async fn put_device_state(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+    req: HttpRequest,
+    form: web::Json<models::TargetState>,
+    drivers: web::Data<drivers::Drivers>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+    let form = form.into_inner();
+
+    let header_versions = if_match_versions(&req)?;
+    // only one precondition applies; the body may repeat a version from the header
+    let expected_versions = match (header_versions, form.version) {
+        (Some(versions), Some(version)) if !versions.contains(&version) => {
+            return Err(AppError::Validation(String::from(
+                "`version` does not match the If-Match header",
+            )));
+        }
+        (_, Some(version)) => Some(vec![version]),
+        (versions, None) => versions,
+    };
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let change = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::set_state_device(
+            &mut conn,
+            device_uid,
+            form.state,
+            expected_versions.as_deref(),
+            &drivers,
+            &client,
+        )
+    })
+    .await??
+    // device was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("device", device_uid))?;
+
+    // device was found; return 200 response with the device and whether it changed
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(device_etag(&change.device)))
+        .json(change))
+}
+
+/// Deletes room by UID.
+///
+/// Its devices are deleted with it unless `?cascade=false` is given, in which
+/// case a room that still has devices is refused with 409. Responds with the
+/// whole removed tree and row counts; `?dry_run=true` only reports them.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a room UID from the request path
+/// - the cascade and dry run options from the query string
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "rooms",
+    params(models::DeleteOptions),
+    responses(
+        (status = 200, description = "OK", body = models::Removal<models::RoomTree>),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[delete("/room/{room_uid}")]
+async fn delete_room(
+    pool: web::Data<DbPool>,
+    room_uid: web::Path<Uuid>,
+    options: web::Query<models::DeleteOptions>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let room_uid = room_uid.into_inner();
+    let options = options.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let removal = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::remove_room_by_id(&mut conn, room_uid, &options, &client)
+    })
+    .await??
+    // room was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("room", room_uid))?;
+
+    // room was removed; return 200 response with JSON formatted tree of the room and counts
+    Ok(HttpResponse::Ok().json(removal))
+}
+
+/// Partially updates room by UID.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a room UID from the request path
+/// - a JSON form containing the fields to change from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "rooms",
+    responses(
+        (status = 200, description = "OK", body = models::Room),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[patch("/room/{room_uid}")]
+async fn patch_room(
+    pool: web::Data<DbPool>,
+    room_uid: web::Path<Uuid>,
+    form: web::Json<models::RoomPatch>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let room_uid = room_uid.into_inner();
+    if let Some(name) = &form.name {
+        validate_name(name)?;
+    }
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let room = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::update_room(&mut conn, room_uid, &form, &client)
+    })
+    .await??
+    // room was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("room", room_uid))?;
+
+    // room was updated; return 200 response with JSON formatted room object
+    Ok(HttpResponse::Ok().json(room))
+}
+
+/// Renames room by UID.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a room UID from the request path
+/// - a JSON form containing the new name from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "rooms",
+    responses(
+        (status = 200, description = "OK", body = models::Room),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[put("/room/{room_uid}/name")]
+async fn rename_room(
+    pool: web::Data<DbPool>,
+    room_uid: web::Path<Uuid>,
+    form: web::Json<models::Rename>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let room_uid = room_uid.into_inner();
+    validate_name(&form.name)?;
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let room = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        let patch = models::RoomPatch {
+            name: Some(form.into_inner().name),
+            ..Default::default()
+        };
+
+        actions::update_room(&mut conn, room_uid, &patch, &client)
+    })
+    .await??
+    // room was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("room", room_uid))?;
+
+    // room was renamed; return 200 response with JSON formatted room object
+    Ok(HttpResponse::Ok().json(room))
+}
+
+/// Moves room to another house.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a room UID from the request path
+/// - a JSON form containing the target house UID from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "rooms",
+    responses(
+        (status = 200, description = "OK", body = models::Room),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[put("/room/{room_uid}/house")]
+async fn move_room(
+    pool: web::Data<DbPool>,
+    room_uid: web::Path<Uuid>,
+    form: web::Json<models::MoveRoom>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let room_uid = room_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let room = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        let patch = models::RoomPatch {
+            house: Some(form.into_inner().house),
+            ..Default::default()
+        };
+
+        actions::update_room(&mut conn, room_uid, &patch, &client)
+    })
+    .await??
+    // room was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("room", room_uid))?;
+
+    // room was moved; return 200 response with JSON formatted room object
+    Ok(HttpResponse::Ok().json(room))
+}
+
+/// Deletes house by UID.
+///
+/// Its rooms are deleted with it unless `?cascade=false` is given, in which
+/// case a house that still has rooms is refused with 409. Responds with the
+/// whole removed tree and row counts; `?dry_run=true` only reports them.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a house UID from the request path
+/// - the cascade and dry run options from the query string
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "houses",
+    params(models::DeleteOptions),
+    responses(
+        (status = 200, description = "OK", body = models::Removal<models::HouseTree>),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[delete("/house/{house_uid}")]
+async fn delete_house(
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+    options: web::Query<models::DeleteOptions>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+    let options = options.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let removal = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::remove_house_by_id(&mut conn, house_uid, &options, &client)
+    })
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
+
+    // house was removed; return 200 response with JSON formatted tree of the house and counts
+    Ok(HttpResponse::Ok().json(removal))
+}
+
+/// Partially updates house by UID.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a house UID from the request path
+/// - a JSON form containing the fields to change from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "houses",
+    responses(
+        (status = 200, description = "OK", body = models::House),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[patch("/house/{house_uid}")]
+async fn patch_house(
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+    form: web::Json<models::HousePatch>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+    if let Some(name) = &form.name {
+        validate_name(name)?;
+    }
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let house = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::update_house(&mut conn, house_uid, &form, &client)
+    })
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
+
+    // house was updated; return 200 response with JSON formatted house object
+    Ok(HttpResponse::Ok().json(house))
+}
+
+/// Renames house by UID.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a house UID from the request path
+/// - a JSON form containing the new name from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "houses",
+    responses(
+        (status = 200, description = "OK", body = models::House),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[put("/house/{house_uid}/name")]
+async fn rename_house(
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+    form: web::Json<models::Rename>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+    validate_name(&form.name)?;
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let house = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        let patch = models::HousePatch {
+            name: Some(form.into_inner().name),
+        };
+
+        actions::update_house(&mut conn, house_uid, &patch, &client)
+    })
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
+
+    // house was renamed; return 200 response with JSON formatted house object
+    Ok(HttpResponse::Ok().json(house))
+}
+
+/// Finds room by UID.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a user UID from the request path
+#[utoipa::path(
+    tag = "rooms",
+    responses(
+        (status = 200, description = "OK", body = models::Room),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/room/{room_uid}")]
+async fn get_room(
+    pool: web::Data<DbPool>,
+    room_uid: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    let room_uid = room_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let room = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::find_room_by_id(&mut conn, room_uid)
+    })
+    .await??
+    // room was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("room", room_uid))?;
+
+    // room was found; return 200 response with JSON formatted room object
+    Ok(HttpResponse::Ok().json(room))
+}
+
+/// Finds house by UID.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a user UID from the request path
+#[utoipa::path(
+    tag = "houses",
+    responses(
+        (status = 200, description = "OK", body = models::House),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/house/{house_uid}")]
+async fn get_house(
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let house = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
         actions::find_house_by_id(&mut conn, house_uid)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??
+    // house was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("house", house_uid))?;
+
+    // house was found; return 200 response with JSON formatted house object
+    Ok(HttpResponse::Ok().json(house))
+}
+
+/// Lists deleted houses, rooms and devices which can still be restored.
+///
+/// Extracts:
+/// - the database pool handle from application data
+#[utoipa::path(
+    tag = "trash",
+    responses(
+        (status = 200, description = "OK", body = models::Trash)
+    )
+)]
+#[get("/trash")]
+async fn get_trash(pool: web::Data<DbPool>) -> Result<impl Responder, AppError> {
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let trash = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::list_trash(&mut conn)
+    })
+    .await??;
+
+    // return 200 response with JSON formatted contents of the trash
+    Ok(HttpResponse::Ok().json(trash))
+}
+
+/// Lists audit log events, oldest first.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - paging and filters by entity, house, action, client and time range from the query string
+#[utoipa::path(
+    tag = "events",
+    params(models::EventQuery),
+    responses(
+        (status = 200, description = "OK", body = Vec<models::Event>),
+        (status = 400, description = "Invalid request", body = ErrorBody)
+    )
+)]
+#[get("/events")]
+async fn list_events(
+    req: HttpRequest,
+    pool: web::Data<DbPool>,
+    query: web::Query<models::EventQuery>,
+) -> Result<impl Responder, AppError> {
+    if let Some(kind) = &query.entity {
+        if ![
+            models::Device::KIND,
+            models::Room::KIND,
+            models::House::KIND,
+        ]
+        .contains(&kind.as_str())
+        {
+            return Err(AppError::Validation(format!("Unknown entity: {kind}")));
+        }
+    }
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let page = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::list_events(&mut conn, &query)
+    })
+    .await??;
+
+    // return 200 response with JSON formatted events, possibly none
+    Ok(page_response(&req, page.next_cursor).json(page.items))
+}
+
+/// Streams changes as server-sent events, for clients that can't use the WebSocket.
+///
+/// Every event is named after the kind of change and carries its id, so a
+/// reconnecting client sending `Last-Event-ID` gets what it missed replayed, or
+/// a `resync` event when that is no longer kept.
+///
+/// Extracts:
+/// - the change hub from application data
+/// - an optional house UID from the query string
+/// - the id of the last event seen from the `Last-Event-ID` header
+#[utoipa::path(
+    tag = "live",
+    params(models::StreamQuery),
+    responses(
+        (status = 200, description = "Stream of changes", content_type = "text/event-stream"),
+        (status = 400, description = "Invalid request", body = ErrorBody)
+    )
+)]
+#[get("/events/stream")]
+async fn get_event_stream(
+    req: HttpRequest,
+    query: web::Query<models::StreamQuery>,
+    hub: web::Data<Addr<live::ChangeHub>>,
+) -> HttpResponse {
+    // ids not handed out by this server are treated like a fresh start
+    let last_event_id = req
+        .headers()
+        .get(LAST_EVENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .body(live::event_stream(
+            hub.get_ref().clone(),
+            query.house,
+            last_event_id,
+        ))
+}
+
+/// Get audit log event by id.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - an event id from the request path
+#[utoipa::path(
+    tag = "events",
+    responses(
+        (status = 200, description = "OK", body = models::Event),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/events/{event_id}")]
+async fn get_event(
+    pool: web::Data<DbPool>,
+    event_id: web::Path<i32>,
+) -> Result<impl Responder, AppError> {
+    let event_id = event_id.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let event = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::find_event_by_id(&mut conn, event_id)
+    })
+    .await??
+    // event was not found; respond with 404
+    .ok_or_else(|| AppError::not_found("event", event_id))?;
+
+    // event was found; return 200 response with JSON formatted event
+    Ok(HttpResponse::Ok().json(event))
+}
+
+/// Opens a WebSocket pushing the changes of subscribed houses, rooms and devices.
+///
+/// Clients send `{"type": "subscribe", "houses": [...], "rooms": [...], "devices": [...]}`
+/// and receive `{"type": "change", ...}` messages; `last_event_id` in the first
+/// subscription replays what was missed while disconnected, or asks for a `resync`
+/// when it is no longer buffered.
+///
+/// Extracts:
+/// - the change hub from application data
+/// - the request and its payload to upgrade the connection
+#[utoipa::path(
+    tag = "live",
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 400, description = "Not a WebSocket handshake")
+    )
+)]
+#[get("/ws")]
+async fn get_ws(
+    req: HttpRequest,
+    stream: web::Payload,
+    hub: web::Data<Addr<live::ChangeHub>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    ws::start(live::WsSession::new(hub.get_ref().clone()), &req, stream)
+}
+
+/// Restores deleted device.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a device UID from the request path
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "trash",
+    responses(
+        (status = 200, description = "OK", body = models::Device),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[post("/device/{device_uid}/restore")]
+async fn restore_device(
+    pool: web::Data<DbPool>,
+    device_uid: web::Path<Uuid>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let device_uid = device_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let restored = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::restore_device_by_id(&mut conn, device_uid, &client)
+    })
+    .await??
+    // device is not in the trash; respond with 404
+    .ok_or_else(|| AppError::not_found("deleted device", device_uid))?;
+
+    // device was restored; return 200 response with JSON formatted device object
+    Ok(HttpResponse::Ok().json(restored))
+}
+
+/// Restores deleted room with the devices deleted together with it.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a room UID from the request path
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "trash",
+    responses(
+        (status = 200, description = "OK", body = models::RoomTree),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[post("/room/{room_uid}/restore")]
+async fn restore_room(
+    pool: web::Data<DbPool>,
+    room_uid: web::Path<Uuid>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let room_uid = room_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let restored = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::restore_room_by_id(&mut conn, room_uid, &client)
+    })
+    .await??
+    // room is not in the trash; respond with 404
+    .ok_or_else(|| AppError::not_found("deleted room", room_uid))?;
+
+    // room was restored; return 200 response with JSON formatted room tree
+    Ok(HttpResponse::Ok().json(restored))
+}
+
+/// Restores deleted house with the rooms and devices deleted together with it.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - a house UID from the request path
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "trash",
+    responses(
+        (status = 200, description = "OK", body = models::HouseTree),
+        (status = 404, description = "Not found", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
+#[post("/house/{house_uid}/restore")]
+async fn restore_house(
+    pool: web::Data<DbPool>,
+    house_uid: web::Path<Uuid>,
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    let house_uid = house_uid.into_inner();
+
+    // use web::block to offload blocking Diesel queries without blocking server thread
+    let restored = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
+
+        actions::restore_house_by_id(&mut conn, house_uid, &client)
+    })
+    .await??
+    // house is not in the trash; respond with 404
+    .ok_or_else(|| AppError::not_found("deleted house", house_uid))?;
 
-    Ok(match house {
-        // house was found; return 200 response with JSON formatted user object
-        Some(house) => HttpResponse::Ok().json(house),
+    // house was restored; return 200 response with JSON formatted house tree
+    Ok(HttpResponse::Ok().json(restored))
+}
+
+/// Exports house reports to files right away.
+///
+/// Extracts:
+/// - the database pool handle from application data
+/// - the export configuration from application data
+/// - the house, format and language to export from the query string
+#[utoipa::path(
+    tag = "reports",
+    params(models::ExportQuery),
+    responses(
+        (status = 201, description = "Created", body = Vec<models::ExportFile>),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[post("/exports")]
+async fn create_export(
+    pool: web::Data<DbPool>,
+    config: web::Data<export::ExportConfig>,
+    query: web::Query<models::ExportQuery>,
+) -> Result<impl Responder, AppError> {
+    let query = query.into_inner();
+    let catalog = match &query.lang {
+        Some(lang) => report_generator::Catalog::get(lang)?,
+        None => report_generator::Catalog::find(&config.lang)
+            .unwrap_or_else(report_generator::Catalog::default_language),
+    };
+
+    // use web::block to offload blocking Diesel queries and file writes without blocking server thread
+    let files = web::block(move || {
+        // note that obtaining a connection from the pool is also potentially blocking
+        let mut conn = pool.get()?;
 
-        // house was not found; return 404 response with error message
-        None => HttpResponse::NotFound().body(format!("No device found with UID: {house_uid}")),
+        let houses = match query.house {
+            Some(house) => {
+                actions::find_house_by_id(&mut conn, house)?
+                    .ok_or_else(|| AppError::not_found("house", house))?;
+                vec![house]
+            }
+            None => match &config.houses {
+                Some(houses) => houses.clone(),
+                None => actions::live_house_ids(&mut conn)?,
+            },
+        };
+        let formats = query
+            .format
+            .map_or_else(|| config.formats.clone(), |format| vec![format]);
+
+        export::export_reports(&mut conn, &config, &houses, &formats, catalog)
+    })
+    .await??;
+
+    // reports were written; return 201 response with the JSON formatted files
+    Ok(HttpResponse::Created().json(files))
+}
+
+/// Lists exported report files, newest first.
+///
+/// Extracts:
+/// - the export configuration from application data
+#[utoipa::path(
+    tag = "reports",
+    responses(
+        (status = 200, description = "OK", body = Vec<models::ExportFile>)
+    )
+)]
+#[get("/exports")]
+async fn get_exports(config: web::Data<export::ExportConfig>) -> Result<impl Responder, AppError> {
+    let files = web::block(move || export::list_exports(&config.dir)).await??;
+
+    Ok(HttpResponse::Ok().json(files))
+}
+
+/// Downloads an exported report file.
+///
+/// Extracts:
+/// - the export configuration from application data
+/// - a file name from the request path
+#[utoipa::path(
+    tag = "reports",
+    responses(
+        (status = 200, description = "OK", body = String),
+        (status = 404, description = "Not found", body = ErrorBody)
+    )
+)]
+#[get("/exports/{name}")]
+async fn get_export(
+    config: web::Data<export::ExportConfig>,
+    name: web::Path<String>,
+) -> Result<impl Responder, AppError> {
+    let name = name.into_inner();
+
+    let (path, format) = export::export_path(&config.dir, &name)
+        .ok_or_else(|| AppError::not_found("export", &name))?;
+    let contents = web::block(move || std::fs::read(path)).await??;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(header::ContentDisposition::attachment(name))
+        .body(contents))
+}
+
+/// Lists supported device kinds and their capabilities.
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 200, description = "OK", body = Vec<models::DeviceKindInfo>)
+    )
+)]
+#[get("/device-kinds")]
+async fn get_device_kinds() -> impl Responder {
+    let kinds: Vec<models::DeviceKindInfo> = models::DeviceKind::ALL
+        .into_iter()
+        .map(models::DeviceKindInfo::from)
+        .collect();
+
+    HttpResponse::Ok().json(kinds)
+}
+
+/// Counts of what the server received from devices.
+///
+/// Extracts:
+/// - the UDP listener counters from application data
+#[utoipa::path(
+    tag = "metrics",
+    responses(
+        (status = 200, description = "OK", body = models::Metrics)
+    )
+)]
+#[get("/metrics")]
+async fn get_metrics(counters: web::Data<udp::Counters>) -> impl Responder {
+    HttpResponse::Ok().json(models::Metrics {
+        udp: counters.snapshot(),
     })
 }
 
@@ -484,21 +2297,40 @@ async fn get_house(
 /// Extracts:
 /// - the database pool handle from application data
 /// - a JSON form containing new device info from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "devices",
+    responses(
+        (status = 201, description = "Created", body = models::Device),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
 #[post("/device")]
 async fn add_device(
     pool: web::Data<DbPool>,
     form: web::Json<models::NewDevice>,
-) -> actix_web::Result<impl Responder> {
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    // unknown device types are rejected with 400
+    let kind = form.typ.parse::<models::DeviceKind>()?;
+    validate_name(&form.name)?;
+
     // use web::block to offload blocking Diesel queries without blocking server thread
     let device = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::insert_new_device(&mut conn, &form.name, &form.typ, &form.address, &form.room)
+        actions::insert_new_device(
+            &mut conn,
+            &form.name,
+            kind,
+            &form.address,
+            &form.room,
+            &client,
+        )
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??;
 
     // deivce was added successfully; return 201 response with new user info
     Ok(HttpResponse::Created().json(device))
@@ -509,21 +2341,31 @@ async fn add_device(
 /// Extracts:
 /// - the database pool handle from application data
 /// - a JSON form containing new device info from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "rooms",
+    responses(
+        (status = 201, description = "Created", body = models::Room),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
 #[post("/room")]
 async fn add_room(
     pool: web::Data<DbPool>,
     form: web::Json<models::NewRoom>,
-) -> actix_web::Result<impl Responder> {
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    validate_name(&form.name)?;
+
     // use web::block to offload blocking Diesel queries without blocking server thread
     let room = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::insert_new_room(&mut conn, &form.name, &form.house)
+        actions::insert_new_room(&mut conn, &form.name, &form.house, &client)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??;
 
     // room was added successfully; return 201 response with new user info
     Ok(HttpResponse::Created().json(room))
@@ -534,21 +2376,31 @@ async fn add_room(
 /// Extracts:
 /// - the database pool handle from application data
 /// - a JSON form containing new device info from the request body
+/// - the identity of the client from the `X-Client-Id` header or its address
+#[utoipa::path(
+    tag = "houses",
+    responses(
+        (status = 201, description = "Created", body = models::House),
+        (status = 400, description = "Invalid request", body = ErrorBody),
+        (status = 409, description = "Conflicts with existing data", body = ErrorBody)
+    )
+)]
 #[post("/house")]
 async fn add_house(
     pool: web::Data<DbPool>,
     form: web::Json<models::NewHouse>,
-) -> actix_web::Result<impl Responder> {
+    client: Client,
+) -> Result<impl Responder, AppError> {
+    validate_name(&form.name)?;
+
     // use web::block to offload blocking Diesel queries without blocking server thread
     let house = web::block(move || {
         // note that obtaining a connection from the pool is also potentially blocking
         let mut conn = pool.get()?;
 
-        actions::insert_new_house(&mut conn, &form.name)
+        actions::insert_new_house(&mut conn, &form.name, &client)
     })
-    .await?
-    // map diesel query errors to a 500 error response
-    .map_err(error::ErrorInternalServerError)?;
+    .await??;
 
     // house was added successfully; return 201 response with new user info
     Ok(HttpResponse::Created().json(house))
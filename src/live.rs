@@ -0,0 +1,548 @@
+use crate::models::{self, Change, Device, Item};
+use actix::prelude::*;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, web::Bytes, HttpMessage};
+use actix_web_actors::ws;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// How many of the latest changes are kept for subscribers resuming after a reconnect.
+pub const BUFFER_SIZE: usize = 1024;
+
+/// How often WebSocket clients are pinged.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a WebSocket client may stay silent before it is disconnected.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How often a comment is written to event streams, so proxies keep them open
+/// and streams of departed clients are noticed.
+const STREAM_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// How long event stream clients wait before reconnecting, in milliseconds.
+const STREAM_RETRY_MS: u64 = 3000;
+
+/// How many events may wait for an event stream client to read them; room for
+/// a full replay of the buffer. A client falling further behind is dropped and
+/// resumes from its `Last-Event-ID`.
+const STREAM_BACKLOG: usize = BUFFER_SIZE + 16;
+
+/// Hands published changes out to the connected sessions and keeps the latest
+/// ones for sessions resuming after a reconnect.
+pub struct ChangeHub {
+    capacity: usize,
+    buffer: VecDeque<Arc<Change>>,
+    last_id: u64,
+    sessions: HashMap<usize, Recipient<Push>>,
+    next_session: usize,
+}
+
+impl ChangeHub {
+    pub fn new(capacity: usize) -> Self {
+        ChangeHub {
+            capacity,
+            buffer: VecDeque::with_capacity(capacity),
+            last_id: 0,
+            sessions: HashMap::new(),
+            next_session: 0,
+        }
+    }
+
+    /// Sends the buffered changes after `after`, or [`Push::Resync`] when some are gone.
+    fn replay(&self, to: &Recipient<Push>, after: u64) {
+        // ids are handed out one by one, so the oldest buffered change tells
+        // whether anything after `after` has been dropped already
+        let oldest = self
+            .buffer
+            .front()
+            .map_or(self.last_id + 1, |change| change.id);
+        if after > self.last_id || after + 1 < oldest {
+            to.do_send(Push::Resync);
+            return;
+        }
+
+        for change in self.buffer.iter().filter(|change| change.id > after) {
+            to.do_send(Push::Change(change.clone()));
+        }
+    }
+}
+
+impl Default for ChangeHub {
+    fn default() -> Self {
+        Self::new(BUFFER_SIZE)
+    }
+}
+
+impl Actor for ChangeHub {
+    type Context = Context<Self>;
+}
+
+/// Changes of a successful request, in the order they were made.
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct Publish(pub Vec<Change>);
+
+/// What the hub sends to a session.
+#[derive(Debug, Clone, Message)]
+#[rtype(result = "()")]
+pub enum Push {
+    Change(Arc<Change>),
+    /// Some of the changes asked to be replayed are no longer buffered, so the
+    /// session has to reload what it shows.
+    Resync,
+}
+
+/// Registers a session, answering with its id in the hub.
+///
+/// With `after` set, the changes after it are replayed first as with [`Replay`],
+/// so a resuming session neither misses nor repeats any.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Connect {
+    pub session: Recipient<Push>,
+    pub after: Option<u64>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect(pub usize);
+
+/// Sends the buffered changes after `after` to a session, or [`Push::Resync`]
+/// when they can't all be replayed.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Replay {
+    pub to: Recipient<Push>,
+    pub after: u64,
+}
+
+impl Handler<Publish> for ChangeHub {
+    type Result = ();
+
+    fn handle(&mut self, Publish(changes): Publish, _: &mut Context<Self>) {
+        for mut change in changes {
+            self.last_id += 1;
+            change.id = self.last_id;
+            let change = Arc::new(change);
+
+            if self.buffer.len() == self.capacity {
+                self.buffer.pop_front();
+            }
+            if self.capacity > 0 {
+                self.buffer.push_back(change.clone());
+            }
+            for session in self.sessions.values() {
+                session.do_send(Push::Change(change.clone()));
+            }
+        }
+    }
+}
+
+impl Handler<Connect> for ChangeHub {
+    type Result = usize;
+
+    fn handle(&mut self, Connect { session, after }: Connect, _: &mut Context<Self>) -> usize {
+        if let Some(after) = after {
+            self.replay(&session, after);
+        }
+        self.next_session += 1;
+        self.sessions.insert(self.next_session, session);
+        self.next_session
+    }
+}
+
+impl Handler<Disconnect> for ChangeHub {
+    type Result = ();
+
+    fn handle(&mut self, Disconnect(id): Disconnect, _: &mut Context<Self>) {
+        self.sessions.remove(&id);
+    }
+}
+
+impl Handler<Replay> for ChangeHub {
+    type Result = ();
+
+    fn handle(&mut self, Replay { to, after }: Replay, _: &mut Context<Self>) {
+        self.replay(&to, after);
+    }
+}
+
+/// Houses, rooms and devices a session receives the changes of.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Subscription {
+    pub houses: BTreeSet<String>,
+    pub rooms: BTreeSet<String>,
+    pub devices: BTreeSet<String>,
+}
+
+impl Subscription {
+    /// Whether the change is about a subscribed house, room or device, or
+    /// something inside them.
+    pub fn matches(&self, change: &Change) -> bool {
+        self.houses.iter().any(|house| change.in_house(house))
+            || change.rooms.iter().any(|room| self.rooms.contains(room))
+            || (change.entity == Device::KIND && self.devices.contains(&change.entity_id))
+    }
+
+    fn add(&mut self, targets: Targets) {
+        self.houses
+            .extend(targets.houses.iter().map(Uuid::to_string));
+        self.rooms.extend(targets.rooms.iter().map(Uuid::to_string));
+        self.devices
+            .extend(targets.devices.iter().map(Uuid::to_string));
+    }
+
+    fn remove(&mut self, targets: Targets) {
+        for house in targets.houses {
+            self.houses.remove(&house.to_string());
+        }
+        for room in targets.rooms {
+            self.rooms.remove(&room.to_string());
+        }
+        for device in targets.devices {
+            self.devices.remove(&device.to_string());
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Targets {
+    #[serde(default)]
+    houses: Vec<Uuid>,
+    #[serde(default)]
+    rooms: Vec<Uuid>,
+    #[serde(default)]
+    devices: Vec<Uuid>,
+}
+
+/// Message from a WebSocket client.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Subscribe {
+        #[serde(flatten)]
+        targets: Targets,
+        /// Id of the last change seen before reconnecting; the changes after
+        /// it are replayed. Only taken from the first subscription.
+        last_event_id: Option<u64>,
+    },
+    Unsubscribe {
+        #[serde(flatten)]
+        targets: Targets,
+    },
+}
+
+/// Message to a WebSocket client.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply<'a> {
+    Subscribed(&'a Subscription),
+    Change(&'a Change),
+    Resync,
+    Error { message: String },
+}
+
+/// WebSocket connection pushing the changes of its subscription.
+pub struct WsSession {
+    hub: Addr<ChangeHub>,
+    id: Option<usize>,
+    subscription: Subscription,
+    heartbeat: Instant,
+}
+
+impl WsSession {
+    pub fn new(hub: Addr<ChangeHub>) -> Self {
+        WsSession {
+            hub,
+            id: None,
+            subscription: Subscription::default(),
+            heartbeat: Instant::now(),
+        }
+    }
+
+    fn send(&self, reply: &Reply, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::to_string(reply) {
+            Ok(text) => ctx.text(text),
+            Err(err) => log::error!("failed to serialize a live message: {err}"),
+        }
+    }
+
+    /// Registers with the hub, which replays the changes after `after` before
+    /// pushing new ones, so none is missed or sent twice.
+    fn connect(&mut self, after: Option<u64>, ctx: &mut ws::WebsocketContext<Self>) {
+        self.hub
+            .send(Connect {
+                session: ctx.address().recipient(),
+                after,
+            })
+            .into_actor(self)
+            .then(|id, session, ctx| {
+                match id {
+                    Ok(id) => session.id = Some(id),
+                    Err(_) => ctx.stop(),
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn handle_request(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::from_str::<Request>(text) {
+            Ok(Request::Subscribe {
+                targets,
+                last_event_id,
+            }) => {
+                self.subscription.add(targets);
+                self.send(&Reply::Subscribed(&self.subscription), ctx);
+                if self.id.is_none() {
+                    self.connect(last_event_id, ctx);
+                } else if last_event_id.is_some() {
+                    // everything since the first subscription is already pushed
+                    self.send(
+                        &Reply::Error {
+                            message: String::from(
+                                "last_event_id is only accepted in the first subscription",
+                            ),
+                        },
+                        ctx,
+                    );
+                }
+            }
+            Ok(Request::Unsubscribe { targets }) => {
+                self.subscription.remove(targets);
+                self.send(&Reply::Subscribed(&self.subscription), ctx);
+            }
+            Err(err) => self.send(
+                &Reply::Error {
+                    message: err.to_string(),
+                },
+                ctx,
+            ),
+        }
+    }
+}
+
+impl Actor for WsSession {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |session, ctx| {
+            if session.heartbeat.elapsed() > CLIENT_TIMEOUT {
+                log::info!("live client timed out");
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+        // the session connects to the hub with its first subscription, which
+        // may resume from a `last_event_id`
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        if let Some(id) = self.id {
+            self.hub.do_send(Disconnect(id));
+        }
+        Running::Stop
+    }
+}
+
+impl Handler<Push> for WsSession {
+    type Result = ();
+
+    fn handle(&mut self, push: Push, ctx: &mut Self::Context) {
+        match push {
+            Push::Change(change) => {
+                if self.subscription.matches(&change) {
+                    self.send(&Reply::Change(&change), ctx);
+                }
+            }
+            Push::Resync => self.send(&Reply::Resync, ctx),
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
+    fn handle(&mut self, message: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        let Ok(message) = message else {
+            ctx.stop();
+            return;
+        };
+
+        self.heartbeat = Instant::now();
+        match message {
+            ws::Message::Ping(bytes) => ctx.pong(&bytes),
+            ws::Message::Pong(_) | ws::Message::Nop => {}
+            ws::Message::Text(text) => self.handle_request(&text, ctx),
+            ws::Message::Binary(_) => self.send(
+                &Reply::Error {
+                    message: String::from("messages must be JSON text"),
+                },
+                ctx,
+            ),
+            ws::Message::Close(reason) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            ws::Message::Continuation(_) => ctx.stop(),
+        }
+    }
+}
+
+/// Server-sent event stream of the changes in one house, or in all of them.
+struct SseSession {
+    hub: Addr<ChangeHub>,
+    id: Option<usize>,
+    house: Option<String>,
+    last_event_id: Option<u64>,
+    events: mpsc::Sender<Bytes>,
+}
+
+impl SseSession {
+    /// Writes to the stream, stopping once the client has gone or stopped reading.
+    fn write(&self, event: String, ctx: &mut Context<Self>) {
+        match self.events.try_send(Bytes::from(event)) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                log::info!("dropping an event stream client which fell behind");
+                ctx.stop();
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => ctx.stop(),
+        }
+    }
+}
+
+impl Actor for SseSession {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(STREAM_KEEP_ALIVE, |session, ctx| {
+            session.write(String::from(": keep-alive\n\n"), ctx);
+        });
+
+        self.hub
+            .send(Connect {
+                session: ctx.address().recipient(),
+                after: self.last_event_id,
+            })
+            .into_actor(self)
+            .then(|id, session, ctx| {
+                match id {
+                    Ok(id) => session.id = Some(id),
+                    Err(_) => ctx.stop(),
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        if let Some(id) = self.id {
+            self.hub.do_send(Disconnect(id));
+        }
+        Running::Stop
+    }
+}
+
+impl Handler<Push> for SseSession {
+    type Result = ();
+
+    fn handle(&mut self, push: Push, ctx: &mut Self::Context) {
+        let event = match push {
+            Push::Change(change) => {
+                if self
+                    .house
+                    .as_ref()
+                    .is_some_and(|house| !change.in_house(house))
+                {
+                    return;
+                }
+                let data = match serde_json::to_string(&*change) {
+                    Ok(data) => data,
+                    Err(err) => {
+                        log::error!("failed to serialize a live message: {err}");
+                        return;
+                    }
+                };
+                format!(
+                    "id: {0}\nevent: {1}\ndata: {data}\n\n",
+                    change.id, change.kind
+                )
+            }
+            Push::Resync => String::from("event: resync\ndata: {}\n\n"),
+        };
+        self.write(event, ctx);
+    }
+}
+
+/// Body of an event stream, fed by its session until the client goes away.
+pub struct EventStream(mpsc::Receiver<Bytes>);
+
+impl MessageBody for EventStream {
+    type Error = Infallible;
+
+    fn size(&self) -> BodySize {
+        BodySize::Stream
+    }
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Bytes, Infallible>>> {
+        self.0.poll_recv(cx).map(|event| event.map(Ok))
+    }
+}
+
+/// Starts streaming the changes in `house`, or in every house, as server-sent
+/// events; a client resuming from `last_event_id` first gets what it missed.
+pub fn event_stream(
+    hub: Addr<ChangeHub>,
+    house: Option<Uuid>,
+    last_event_id: Option<u64>,
+) -> EventStream {
+    let (events, stream) = mpsc::channel(STREAM_BACKLOG);
+    let _ = events.try_send(Bytes::from(format!("retry: {STREAM_RETRY_MS}\n\n")));
+    SseSession {
+        hub,
+        id: None,
+        house: house.map(|house| house.to_string()),
+        last_event_id,
+        events,
+    }
+    .start();
+    EventStream(stream)
+}
+
+/// Publishes the changes made by a request once it has succeeded, so changes
+/// rolled back on an error never reach live subscribers.
+pub async fn publish_changes(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let hub = req.app_data::<web::Data<Addr<ChangeHub>>>().cloned();
+    let res = next.call(req).await?;
+
+    if res.status().is_success() {
+        let actor = res
+            .request()
+            .extensions()
+            .get::<Arc<models::Actor>>()
+            .cloned();
+        if let (Some(hub), Some(actor)) = (hub, actor) {
+            let changes = actor.take_changes();
+            if !changes.is_empty() {
+                hub.do_send(Publish(changes));
+            }
+        }
+    }
+
+    Ok(res)
+}
@@ -1,74 +1,144 @@
 extern crate diesel;
 
 use crate::handlers::*;
+use actix::Actor;
 use actix_cors::Cors;
 use actix_web::{middleware, web, App, HttpServer};
 use diesel::{connection::SimpleConnection, prelude::*, r2d2};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 mod actions;
+mod drivers;
+mod error;
+mod export;
 mod handlers;
+mod live;
 mod models;
 pub mod report_generator;
+mod retention;
 mod schema;
+mod udp;
 /// Short-hand for the database pool type to use throughout the app.
 type DbPool = r2d2::Pool<r2d2::ConnectionManager<SqliteConnection>>;
 
+/// Migrations from the `migrations` directory, applied on startup.
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().ok();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
     // initialize DB pool outside of `HttpServer::new` so that it is shared across all workers
     let pool = initialize_db_pool();
-    let conn = pool.get();
-    let _ = conn.unwrap().batch_execute("PRAGMA foreign_keys = 1;");
+
+    retention::spawn(pool.clone(), retention::RetentionPolicy::from_env());
+
+    let export_config = export::ExportConfig::from_env();
+    export::spawn(pool.clone(), export_config.clone());
+
+    // changes are pushed to live subscribers of every worker through one hub
+    let hub = live::ChangeHub::default().start();
+
+    let drivers = drivers::Drivers::from_env();
+    drivers::spawn(pool.clone(), drivers.clone(), hub.clone());
+
+    // thermometers push their readings as UDP datagrams
+    let udp_counters = std::sync::Arc::new(udp::Counters::default());
+    udp::spawn(
+        pool.clone(),
+        udp::UdpConfig::from_env(),
+        udp_counters.clone(),
+        hub.clone(),
+    );
+
+    let legacy_get_routes = legacy_get_routes_enabled();
+    if legacy_get_routes {
+        log::warn!("deprecated GET routes for removal and state toggle are enabled");
+    }
 
     log::info!("starting HTTP server at http://localhost:8080");
+    log::info!("API docs at http://localhost:8080/api/v1/docs");
 
     HttpServer::new(move || {
         App::new()
             // add DB pool handle to app data; enables use of `web::Data<DbPool>` extractor
             .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(export_config.clone()))
+            .app_data(web::Data::new(hub.clone()))
+            .app_data(web::Data::new(drivers.clone()))
+            .app_data(web::Data::from(udp_counters.clone()))
+            // report extractor failures in the same JSON shape as handler errors
+            .app_data(error::path_config())
+            .app_data(error::json_config())
+            .app_data(error::query_config())
             // add request logger middleware
             .wrap(middleware::Logger::default())
+            // push the changes of successful requests to live subscribers
+            .wrap(middleware::from_fn(live::publish_changes))
             .wrap(Cors::default().allow_any_origin())
             // add route handlers
-            .service(get_device)
-            .service(add_device)
-            .service(get_room)
-            .service(add_room)
-            .service(get_house)
-            .service(add_house)
-            .service(rem_house)
-            .service(rem_room)
-            .service(rem_device)
-            .service(get_devices_report)
-            .service(get_list_houses)
-            .service(get_list_rooms)
-            .service(get_list_devices)
-            .service(change_state_device)
-            .service(get_devices_list)
-            .service(get_device_var)
-            .service(get_rooms_list)
+            .service(web::scope("/api/v1").configure(configure_api))
+            .service(
+                web::scope("")
+                    .wrap(middleware::DefaultHeaders::new().add(("Deprecation", "true")))
+                    .configure(configure_unversioned_routes)
+                    .configure(|cfg| {
+                        if legacy_get_routes {
+                            configure_legacy_routes(cfg);
+                        }
+                    }),
+            )
     })
     .bind(("127.0.0.1", 8080))?
     .run()
     .await
 }
 
-/// Initialize database connection pool based on `DATABASE_URL` environment variable.
+/// Whether the deprecated mutating GET routes are mounted, from the
+/// `LEGACY_GET_ROUTES` environment variable (enabled unless set to `0` or `false`).
+fn legacy_get_routes_enabled() -> bool {
+    std::env::var("LEGACY_GET_ROUTES").map_or(true, |value| value != "0" && value != "false")
+}
+
+/// Per-connection SQLite settings.
+///
+/// SQLite keeps foreign key enforcement off unless every connection asks for it.
+#[derive(Debug)]
+struct ConnectionOptions;
+
+impl r2d2::CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Initialize database connection pool based on `DATABASE_URL` environment variable
+/// and bring the schema up to date.
 ///
 /// See more: <https://docs.rs/diesel/latest/diesel/r2d2/index.html>.
 fn initialize_db_pool() -> DbPool {
     let conn_spec = std::env::var("DATABASE_URL").expect("DATABASE_URL should be set");
     let manager = r2d2::ConnectionManager::<SqliteConnection>::new(conn_spec);
 
-    r2d2::Pool::builder()
+    let pool = r2d2::Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
         .build(manager)
-        .expect("database URL should be valid path to SQLite DB file")
+        .expect("database URL should be valid path to SQLite DB file");
+
+    pool.get()
+        .expect("couldn't get db connection from pool")
+        .run_pending_migrations(MIGRATIONS)
+        .expect("database migrations should apply cleanly");
+
+    pool
 }
 
 #[cfg(test)]
 mod tests {
-    use actix_web::{http::StatusCode, test};
+    use actix_web::{
+        http::{header, StatusCode},
+        test,
+    };
     use uuid::Uuid;
 
     use super::*;
@@ -83,6 +153,7 @@ mod tests {
         let app = test::init_service(
             App::new()
                 .app_data(web::Data::new(pool.clone()))
+                .app_data(error::path_config())
                 .wrap(middleware::Logger::default())
                 .service(get_device)
                 .service(add_device)
@@ -94,11 +165,8 @@ mod tests {
         let req = test::TestRequest::get().uri("/device/123").to_request();
         let res = test::call_service(&app, req).await;
         assert_eq!(res.status(), StatusCode::NOT_FOUND);
-        let body = test::read_body(res).await;
-        assert!(
-            body.starts_with(b"UUID parsing failed"),
-            "unexpected body: {body:?}",
-        );
+        let body: error::ErrorBody = test::read_body_json(res).await;
+        assert_eq!(body.code, "not_found");
 
         // try to find a non-existent user
         let req = test::TestRequest::get()
@@ -106,20 +174,60 @@ mod tests {
             .to_request();
         let res = test::call_service(&app, req).await;
         assert_eq!(res.status(), StatusCode::NOT_FOUND);
-        let body = test::read_body(res).await;
+        let body: error::ErrorBody = test::read_body_json(res).await;
+        assert_eq!(body.code, "not_found");
         assert!(
-            body.starts_with(b"No device found"),
-            "unexpected body: {body:?}",
+            body.message.starts_with("No device found"),
+            "unexpected message: {0}",
+            body.message,
         );
 
+        // devices can only be added to existing rooms
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test device",
+                "Socket",
+                "192.168.0.1",
+                Uuid::nil().to_string(),
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let body: error::ErrorBody = test::read_body_json(res).await;
+        assert_eq!(body.code, "foreign_key_violation");
+
+        // unknown device types are rejected
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test device",
+                "Toaster",
+                "192.168.0.1",
+                Uuid::nil().to_string(),
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: error::ErrorBody = test::read_body_json(res).await;
+        assert_eq!(body.code, "validation_failed");
+
+        // reports of unknown houses are not found
+        let req = test::TestRequest::get()
+            .uri(&format!("/report/{}", Uuid::nil()))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
         // create new user
+        let (house, room) = create_test_room(&pool);
         let req = test::TestRequest::post()
             .uri("/device")
             .set_json(models::NewDevice::new(
                 "Test device",
                 "Socket",
                 "192.168.0.1",
-                "First",
+                &room.id,
             ))
             .to_request();
         let res: models::Device = test::call_and_read_body_json(&app, req).await;
@@ -133,9 +241,2817 @@ mod tests {
         assert_eq!(res.name, "Test device");
 
         // delete new user from table
-        use crate::schema::devices::dsl::*;
-        diesel::delete(devices.filter(id.eq(res.id)))
-            .execute(&mut pool.get().expect("couldn't get db connection from pool"))
-            .expect("couldn't delete test device from table");
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn device_kinds() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(add_room)
+                .service(add_house)
+                .service(get_device_kinds),
+        )
+        .await;
+
+        // every kind is listed with its capabilities
+        let req = test::TestRequest::get().uri("/device-kinds").to_request();
+        let kinds: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(kinds.len(), models::DeviceKind::ALL.len());
+        assert_eq!(kinds[0]["kind"], "socket");
+        assert_eq!(kinds[0]["capabilities"][0], "switchable");
+
+        let req = test::TestRequest::post()
+            .uri("/house")
+            .set_json(models::NewHouse::new(format!(
+                "Test house {}",
+                Uuid::new_v4()
+            )))
+            .to_request();
+        let house: models::House = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/room")
+            .set_json(models::NewRoom::new("Test room", &house.id))
+            .to_request();
+        let room: models::Room = test::call_and_read_body_json(&app, req).await;
+
+        // unknown types are rejected
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test device",
+                "Toaster",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // legacy spellings are normalised
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test device",
+                "SmartThermometer",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(device.type_, models::DeviceKind::Thermometer);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn device_readings() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(get_device_var)
+                .service(get_device_readings)
+                .service(add_device_readings),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test socket",
+                "socket",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        // a socket reports power and energy
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", device.id))
+            .set_json(vec![
+                models::NewReading::new(models::Metric::Power, 12.6, None),
+                models::NewReading::new(models::Metric::Energy, 1500.0, Some("Wh")),
+            ])
+            .to_request();
+        let readings: Vec<models::Reading> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(readings.len(), 2);
+        assert_eq!(readings[0].unit, "W");
+        assert_eq!(readings[1].unit, "Wh");
+
+        // but no temperature, and only in known units
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", device.id))
+            .set_json(vec![models::NewReading::new(
+                models::Metric::Temperature,
+                21.5,
+                None,
+            )])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", device.id))
+            .set_json(vec![models::NewReading::new(
+                models::Metric::Power,
+                1.0,
+                Some("hp"),
+            )])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // readings are listed per metric
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/readings", device.id))
+            .to_request();
+        let readings: Vec<models::Reading> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(readings.len(), 2);
+
+        // the primary metric is still visible through the legacy view
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/var", device.id))
+            .to_request();
+        let variable: i32 = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(variable, 13);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/readings", Uuid::nil()))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn device_history() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(add_device_readings)
+                .service(get_device_history),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test thermometer",
+                "thermometer",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        let base = (actions::unix_now() / 600 - 2) * 600;
+        let samples = [(base, 20.0), (base + 10, 22.0), (base + 700, 25.0)]
+            .into_iter()
+            .map(|(time, value)| models::NewReading {
+                recorded_at: Some(time),
+                ..models::NewReading::new(models::Metric::Temperature, value, None)
+            })
+            .collect::<Vec<_>>();
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", device.id))
+            .set_json(samples)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // samples are grouped into buckets
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/device/{}/history?from={base}&to={}&step=600",
+                device.id,
+                base + 1200
+            ))
+            .to_request();
+        let history: models::History = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(history.metric, models::Metric::Temperature);
+        assert_eq!(history.points.len(), 2);
+        assert_eq!(history.points[0].time, base);
+        assert_eq!(history.points[0].samples, 2);
+        assert_eq!(history.points[0].min, 20.0);
+        assert_eq!(history.points[0].max, 22.0);
+        assert_eq!(history.points[0].avg, 21.0);
+        assert_eq!(history.points[1].unit, "°C");
+        assert_eq!(history.points[1].samples, 1);
+
+        // the range has to make sense
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/device/{}/history?from={base}&to={base}",
+                device.id
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        for range in [
+            format!("to={0}", i64::MIN),
+            format!("from={0}&to={1}", i64::MIN, i64::MAX),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/device/{}/history?{range}", device.id))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/history?metric=power", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // expired samples are dropped by the retention policy
+        let expired = models::NewReading {
+            recorded_at: Some(base - 40 * 24 * 60 * 60),
+            ..models::NewReading::new(models::Metric::Temperature, 18.0, None)
+        };
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", device.id))
+            .set_json(vec![expired])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut conn = pool.get().expect("couldn't get db connection from pool");
+        let removed =
+            actions::apply_history_retention(&mut conn, &retention::RetentionPolicy::default())
+                .expect("couldn't apply retention policy");
+        assert!(removed >= 1);
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/device/{}/history?from={}&to={base}&step=86400",
+                device.id,
+                base - 50 * 24 * 60 * 60
+            ))
+            .to_request();
+        let history: models::History = test::call_and_read_body_json(&app, req).await;
+        assert!(history.points.is_empty());
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn mutation_verbs() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .wrap(middleware::Logger::default())
+                .service(get_device)
+                .service(add_device)
+                .service(delete_device)
+                .service(patch_device)
+                .service(put_device_state)
+                .service(get_room)
+                .service(patch_room)
+                .service(delete_room)
+                .service(get_house)
+                .service(patch_house)
+                .service(delete_house)
+                .configure(configure_legacy_routes),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test lamp",
+                "lamp",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert!(!device.state);
+
+        // partial update only touches the given fields
+        let req = test::TestRequest::patch()
+            .uri(&format!("/device/{}", device.id))
+            .set_json(models::DevicePatch {
+                name: Some("Renamed lamp".to_owned()),
+                state: Some(true),
+                ..Default::default()
+            })
+            .to_request();
+        let patched: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(patched.name, "Renamed lamp");
+        assert!(patched.state);
+        assert_eq!(patched.address, device.address);
+
+        // explicit state is idempotent
+        for changed in [true, false] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/device/{}/state", device.id))
+                .set_json(models::TargetState {
+                    state: false,
+                    version: None,
+                })
+                .to_request();
+            let res: models::StateChange = test::call_and_read_body_json(&app, req).await;
+            assert!(!res.device.state);
+            assert_eq!(res.changed, changed);
+        }
+
+        // and can be guarded by the device version
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let etag = res.headers().get("etag").unwrap().clone();
+        let current: models::Device = test::read_body_json(res).await;
+        assert_eq!(etag, format!("\"{}\"", current.version).as_str());
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/state", device.id))
+            .insert_header(("If-Match", "\"1\""))
+            .set_json(models::TargetState {
+                state: true,
+                version: None,
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+        // tags which aren't versions, or disagree with the body, are refused
+        for (tag, version) in [
+            ("\"abc\"", None),
+            ("W/\"1\"", None),
+            ("abc", None),
+            ("\"1\"", Some(current.version)),
+        ] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/device/{}/state", device.id))
+                .insert_header(("If-Match", tag))
+                .set_json(models::TargetState {
+                    state: true,
+                    version,
+                })
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{tag}");
+        }
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/state", device.id))
+            .insert_header(("If-Match", etag))
+            .set_json(models::TargetState {
+                state: true,
+                version: Some(current.version),
+            })
+            .to_request();
+        let res: models::StateChange = test::call_and_read_body_json(&app, req).await;
+        assert!(res.changed);
+        assert_eq!(res.device.version, current.version + 1);
+
+        // the legacy toggle still works but is marked as deprecated
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/state", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get("deprecation").unwrap(), "true");
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/room/{}", room.id))
+            .set_json(models::RoomPatch {
+                name: Some("Renamed room".to_owned()),
+                ..Default::default()
+            })
+            .to_request();
+        let patched: models::Room = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(patched.name, "Renamed room");
+        assert_eq!(patched.house, house.id);
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/house/{}", house.id))
+            .set_json(models::HousePatch {
+                name: Some("Renamed house".to_owned()),
+            })
+            .to_request();
+        let patched: models::House = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(patched.name, "Renamed house");
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/room/{}", room.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/house/{}", house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/house/{}", house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // deleted entities stay in the trash
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn cascade_deletes() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(delete_device)
+                .service(get_room)
+                .service(add_room)
+                .service(delete_room)
+                .service(delete_house),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test socket",
+                "socket",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        // parents with children are kept when cascading is off
+        let req = test::TestRequest::delete()
+            .uri(&format!("/house/{}?cascade=false", house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/room/{}?cascade=false", room.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let body: error::ErrorBody = test::read_body_json(res).await;
+        assert_eq!(body.code, "conflict");
+
+        // deleting returns the removed entity once, then reports it missing
+        let req = test::TestRequest::delete()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let removed: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(removed.id, device.id);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let body: error::ErrorBody = test::read_body_json(res).await;
+        assert_eq!(body.code, "not_found");
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/room/{}?cascade=false", room.id))
+            .to_request();
+        let removed: models::Room = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(removed.id, room.id);
+
+        // by default the house takes its rooms with it
+        let req = test::TestRequest::post()
+            .uri("/room")
+            .set_json(models::NewRoom::new("Second room", &house.id))
+            .to_request();
+        let other_room: models::Room = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Other socket",
+                "socket",
+                "192.168.0.2",
+                &other_room.id,
+            ))
+            .to_request();
+        let other_device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        // a dry run previews the whole tree without removing anything
+        let req = test::TestRequest::delete()
+            .uri(&format!("/house/{}?dry_run=true", house.id))
+            .to_request();
+        let preview: models::Removal<models::HouseTree> =
+            test::call_and_read_body_json(&app, req).await;
+        assert!(preview.dry_run);
+        assert_eq!(preview.tree.rooms.len(), 1);
+        assert_eq!(preview.tree.rooms[0].devices[0].id, other_device.id);
+        assert_eq!(
+            preview.counts,
+            models::CascadeCounts {
+                rooms: 1,
+                devices: 1,
+                ..Default::default()
+            }
+        );
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/room/{}", other_room.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/house/{}", house.id))
+            .to_request();
+        let removed: models::Removal<models::HouseTree> =
+            test::call_and_read_body_json(&app, req).await;
+        assert!(!removed.dry_run);
+        assert_eq!(removed.tree.house.id, house.id);
+        assert_eq!(removed.tree.rooms[0].devices[0].id, other_device.id);
+        assert_eq!(removed.counts, preview.counts);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/room/{}", other_room.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/house/{}", house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // deleted entities stay in the trash
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn house_tree() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(error::path_config())
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(get_house_tree),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let empty_room = {
+            let mut conn = pool.get().expect("couldn't get db connection from pool");
+            actions::insert_new_room(
+                &mut conn,
+                "Another room",
+                &house.id,
+                &models::Actor::new("test"),
+            )
+            .expect("couldn't insert test room")
+        };
+
+        let mut ids = Vec::new();
+        for name in ["Socket", "Lamp"] {
+            let req = test::TestRequest::post()
+                .uri("/device")
+                .set_json(models::NewDevice::new(
+                    name,
+                    "socket",
+                    "192.168.0.1",
+                    &room.id,
+                ))
+                .to_request();
+            let device: models::Device = test::call_and_read_body_json(&app, req).await;
+            ids.push(device.id);
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/house/{}/tree", house.id))
+            .to_request();
+        let tree: models::HouseTree = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(tree.house.id, house.id);
+        // rooms and devices are ordered by name; empty rooms are kept
+        assert_eq!(
+            tree.rooms
+                .iter()
+                .map(|room| room.room.id.as_str())
+                .collect::<Vec<_>>(),
+            [&empty_room.id, &room.id]
+        );
+        assert!(tree.rooms[0].devices.is_empty());
+        assert_eq!(
+            tree.rooms[1]
+                .devices
+                .iter()
+                .map(|device| device.id.as_str())
+                .collect::<Vec<_>>(),
+            [&ids[1], &ids[0]]
+        );
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/house/{}/tree", Uuid::new_v4()))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn house_report_formats() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(error::query_config())
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(add_device_readings)
+                .service(get_devices_report),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        {
+            let mut conn = pool.get().expect("couldn't get db connection from pool");
+            actions::insert_new_room(
+                &mut conn,
+                "Empty room",
+                &house.id,
+                &models::Actor::new("test"),
+            )
+            .expect("couldn't insert test room");
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Kettle, \"big\"",
+                "socket",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", device.id))
+            .set_json(vec![models::NewReading::new(
+                models::Metric::Power,
+                1500.0,
+                None,
+            )])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // JSON by default; an empty room is part of the report
+        let req = test::TestRequest::get()
+            .uri(&format!("/report/{}", house.id))
+            .to_request();
+        let report: models::HouseReport = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(report.house.id, house.id);
+        assert_eq!(report.rooms.len(), 2);
+        assert!(report.rooms[0].devices.is_empty());
+        assert_eq!(report.rooms[1].devices[0].device.id, device.id);
+        assert_eq!(report.rooms[1].devices[0].readings[0].value, 1500.0);
+
+        for (accept, query, content_type, expected) in [
+            ("text/plain", "", "text/plain", "Нет устройств"),
+            (
+                "text/markdown",
+                "",
+                "text/markdown",
+                "| Kettle, \"big\" | socket |",
+            ),
+            (
+                "text/html",
+                "",
+                "text/html",
+                "<td>Kettle, &quot;big&quot;</td>",
+            ),
+            (
+                "text/html",
+                "?format=csv",
+                "text/csv",
+                "\"Kettle, \"\"big\"\"\"",
+            ),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/report/{0}{query}", house.id))
+                .insert_header((header::ACCEPT, accept))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+            let actual_type = res.headers().get(header::CONTENT_TYPE).unwrap();
+            assert!(
+                actual_type.to_str().unwrap().starts_with(content_type),
+                "{accept}{query}: {actual_type:?}"
+            );
+            let body = String::from_utf8(test::read_body(res).await.to_vec()).unwrap();
+            assert!(body.contains(expected), "{accept}{query}: {body}");
+        }
+
+        // text is localised by lang, then Accept-Language; Russian by default
+        for (accept_language, query, expected) in [
+            ("en-GB,ru;q=0.5", "", "Report on house"),
+            ("de,en;q=0.8", "", "Report on house"),
+            ("de", "", "Отчет по дому"),
+            ("en", "&lang=ru", "Отчет по дому"),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/report/{0}?format=text{query}", house.id))
+                .insert_header((header::ACCEPT_LANGUAGE, accept_language))
+                .to_request();
+            let body =
+                String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+            assert!(
+                body.starts_with(expected),
+                "{accept_language}{query}: {body}"
+            );
+        }
+
+        for query in ["format=pdf", "lang=xx"] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/report/{0}?{query}", house.id))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{query}");
+        }
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn house_energy() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .app_data(error::query_config())
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(add_device_readings)
+                .service(put_device_state)
+                .service(get_house_energy),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let now = actions::unix_now();
+
+        let mut ids = Vec::new();
+        for (name, kind) in [("Heater", "socket"), ("Thermometer", "thermometer")] {
+            let req = test::TestRequest::post()
+                .uri("/device")
+                .set_json(models::NewDevice::new(name, kind, "192.168.0.1", &room.id))
+                .to_request();
+            let device: models::Device = test::call_and_read_body_json(&app, req).await;
+            ids.push(device.id);
+        }
+
+        // 1 kW for half an hour, then 2 kW, the second sample in kW
+        let mut first = models::NewReading::new(models::Metric::Power, 1000.0, None);
+        first.recorded_at = Some(now - 3600);
+        let mut second = models::NewReading::new(models::Metric::Power, 2.0, Some("kW"));
+        second.recorded_at = Some(now - 1800);
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", ids[0]))
+            .set_json(vec![first, second])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // switching is recorded along with the initial state
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/state", ids[0]))
+            .set_json(models::TargetState {
+                state: true,
+                version: None,
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        {
+            use crate::schema::state_changes::dsl::*;
+
+            let mut conn = pool.get().expect("couldn't get db connection from pool");
+            let recorded = state_changes
+                .filter(device.eq(&ids[0]))
+                .order(id)
+                .select(state)
+                .load::<bool>(&mut conn)
+                .expect("couldn't load state changes");
+            assert_eq!(recorded, [false, true]);
+
+            // backdate the switching to get a known on-time
+            diesel::delete(state_changes.filter(device.eq(&ids[0])))
+                .execute(&mut conn)
+                .expect("couldn't clear state changes");
+            for (on, at) in [(true, now - 3000), (false, now - 1200)] {
+                diesel::insert_into(state_changes)
+                    .values(&models::StateSample {
+                        device: ids[0].clone(),
+                        state: on,
+                        changed_at: at,
+                    })
+                    .execute(&mut conn)
+                    .expect("couldn't insert state change");
+            }
+        }
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/house/{0}/energy?from={1}&to={2}",
+                house.id,
+                now - 3600,
+                now - 600
+            ))
+            .to_request();
+        let report: models::EnergyReport = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(report.house.id, house.id);
+        // only the socket meters power
+        assert_eq!(report.rooms[0].devices.len(), 1);
+        let usage = report.rooms[0].devices[0].usage;
+        // power only counts while the socket is on
+        assert!((usage.energy_kwh - (20.0 / 60.0 + 2.0 * 10.0 / 60.0)).abs() < 1e-9);
+        assert_eq!(usage.peak_w, 2000.0);
+        assert_eq!(usage.on_time_secs, 1800);
+        assert_eq!(report.usage, usage);
+        assert_eq!((report.from, report.to), (now - 3600, now - 600));
+
+        // the sample before the window holds into it
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/house/{0}/energy?from={1}&to={2}",
+                house.id,
+                now - 2400,
+                now - 1800
+            ))
+            .to_request();
+        let report: models::EnergyReport = test::call_and_read_body_json(&app, req).await;
+        let usage = report.rooms[0].devices[0].usage;
+        assert!((usage.energy_kwh - 10.0 / 60.0).abs() < 1e-9);
+        assert_eq!(usage.peak_w, 1000.0);
+
+        // the meter counts from its reading interpolated at the start of the window
+        let mut first = models::NewReading::new(models::Metric::Energy, 10.0, None);
+        first.recorded_at = Some(now - 3000);
+        let mut second = models::NewReading::new(models::Metric::Energy, 12.0, None);
+        second.recorded_at = Some(now - 1000);
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", ids[0]))
+            .set_json(vec![first, second])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/house/{0}/energy?from={1}&to={2}",
+                house.id,
+                now - 2000,
+                now - 600
+            ))
+            .to_request();
+        let report: models::EnergyReport = test::call_and_read_body_json(&app, req).await;
+        let usage = report.rooms[0].devices[0].usage;
+        assert!((usage.energy_kwh - 1.0).abs() < 1e-9, "{usage:?}");
+
+        // a window reaching into the future is reported up to now
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/house/{0}/energy?from={1}&to={2}",
+                house.id,
+                now - 3600,
+                now + 3600
+            ))
+            .to_request();
+        let report: models::EnergyReport = test::call_and_read_body_json(&app, req).await;
+        assert!((now..now + 60).contains(&report.to));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/house/{0}/energy?to={1}", house.id, i64::MIN))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/house/{0}/energy?format=text&lang=en", house.id))
+            .to_request();
+        let body = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert!(body.starts_with("Energy use of house"), "{body}");
+        assert!(body.contains("- Heater:"), "{body}");
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/house/{0}/energy?from={now}&to={now}", house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/house/{0}/energy", Uuid::new_v4()))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn report_exports() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+        let config = export::ExportConfig {
+            dir: std::env::temp_dir().join(format!("exports-{}", Uuid::new_v4())),
+            keep: 2,
+            ..Default::default()
+        };
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config.clone()))
+                .app_data(error::query_config())
+                .wrap(middleware::Logger::default())
+                .service(create_export)
+                .service(get_exports)
+                .service(get_export),
+        )
+        .await;
+
+        let (house, _room) = create_test_room(&pool);
+
+        // only the newest files of a house and format are kept
+        for _ in 0..3 {
+            let req = test::TestRequest::post()
+                .uri(&format!("/exports?house={0}&format=csv", house.id))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::CREATED);
+            let files: Vec<models::ExportFile> = test::read_body_json(res).await;
+            assert_eq!(files.len(), 1);
+            assert_eq!(files[0].format, models::ReportFormat::Csv);
+            actix_web::rt::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+
+        let req = test::TestRequest::get().uri("/exports").to_request();
+        let files: Vec<models::ExportFile> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(files.len(), 2);
+        assert!(files[0].created_at > files[1].created_at);
+        assert!(files.iter().all(|file| file.house == house.id));
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/exports/{}", files[0].name))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res
+            .headers()
+            .get(header::CONTENT_DISPOSITION)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("attachment"));
+        let body = test::read_body(res).await;
+        assert!(body.starts_with(b"house,room,"));
+
+        // only export files can be downloaded, and only houses which exist exported
+        let req = test::TestRequest::get().uri("/exports/passwd").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/exports?house={}", Uuid::new_v4()))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // files made within the same millisecond don't overwrite each other
+        let other = Uuid::new_v4();
+        let now = chrono::Utc::now();
+        let names: Vec<String> = (0..2)
+            .map(|_| {
+                export::create_file(&config.dir, other, models::ReportFormat::Csv, now)
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        assert_ne!(names[0], names[1]);
+        let files = export::list_exports(&config.dir).unwrap();
+        assert_eq!(
+            files
+                .iter()
+                .filter(|file| file.house == other.to_string())
+                .count(),
+            2
+        );
+
+        std::fs::remove_dir_all(&config.dir).ok();
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn audit_events() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .app_data(error::query_config())
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(patch_device)
+                .service(put_device_state)
+                .service(delete_device)
+                .service(restore_device)
+                .service(list_events)
+                .service(get_event),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let since = actions::unix_now();
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .insert_header(("X-Client-Id", "kitchen-panel"))
+            .set_json(models::NewDevice::new(
+                "Lamp",
+                "lamp",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/device/{}", device.id))
+            .insert_header(("X-Client-Id", "kitchen-panel"))
+            .set_json(models::DevicePatch {
+                name: Some(String::from("Desk lamp")),
+                ..Default::default()
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // switching to the state a device is already in is not logged
+        for state in [true, true] {
+            let req = test::TestRequest::put()
+                .uri(&format!("/device/{}/state", device.id))
+                .insert_header(("X-Client-Id", "phone"))
+                .set_json(models::TargetState {
+                    state,
+                    version: None,
+                })
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+
+        // without a client id the request is attributed to the client address
+        let req = test::TestRequest::delete()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/restore", device.id))
+            .peer_addr("10.0.0.7:40000".parse().unwrap())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/events?house={}", house.id))
+            .to_request();
+        let events: Vec<models::Event> = test::call_and_read_body_json(&app, req).await;
+        let log: Vec<_> = events
+            .iter()
+            .map(|event| (event.action, event.entity.as_str(), event.client.as_str()))
+            .collect();
+        assert_eq!(
+            log,
+            [
+                (models::EventAction::Create, "house", "test"),
+                (models::EventAction::Create, "room", "test"),
+                (models::EventAction::Create, "device", "kitchen-panel"),
+                (models::EventAction::Update, "device", "kitchen-panel"),
+                (models::EventAction::State, "device", "phone"),
+                (models::EventAction::Delete, "device", "unknown"),
+                (models::EventAction::Restore, "device", "10.0.0.7"),
+            ]
+        );
+        let update = &events[3];
+        assert_eq!(update.entity_id, device.id);
+        assert_eq!(update.old_value.as_ref().unwrap().0["name"], "Lamp");
+        assert_eq!(update.new_value.as_ref().unwrap().0["name"], "Desk lamp");
+        assert!(events[2].old_value.is_none());
+        assert!(events[5].new_value.is_none());
+
+        // filters combine; pages continue after the last event
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/events?entity=device&entity_id={0}&from={since}&limit=2",
+                device.id
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let next = res
+            .headers()
+            .get("x-next-cursor")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let page: Vec<models::Event> = test::read_body_json(res).await;
+        assert_eq!(page.len(), 2);
+        assert_eq!(next, events[3].id.to_string());
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/events?entity=device&entity_id={0}&limit=10&cursor={next}",
+                device.id
+            ))
+            .to_request();
+        let page: Vec<models::Event> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.len(), 3);
+        assert_eq!(page[0].id, events[4].id);
+
+        // a cursor past any event id doesn't wrap around to the start
+        let req = test::TestRequest::get()
+            .uri(&format!("/events?cursor={0}", i64::from(i32::MAX) + 1))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/events?house={0}&action=state&client=phone",
+                house.id
+            ))
+            .to_request();
+        let page: Vec<models::Event> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(page.len(), 1);
+        let req = test::TestRequest::get()
+            .uri(&format!("/events?house={0}&to={1}", house.id, since - 60))
+            .to_request();
+        let page: Vec<models::Event> = test::call_and_read_body_json(&app, req).await;
+        assert!(page.is_empty());
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/events/{}", events[4].id))
+            .to_request();
+        let event: models::Event = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(event.new_value.unwrap().0["state"], true);
+
+        let req = test::TestRequest::get()
+            .uri("/events?entity=garage")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let req = test::TestRequest::get()
+            .uri(&format!("/events/{}", i32::MAX))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    /// Stands in for a live session, keeping whatever the hub pushes to it.
+    #[derive(Default)]
+    struct Collector(Vec<live::Push>);
+
+    impl actix::Actor for Collector {
+        type Context = actix::Context<Self>;
+    }
+
+    impl actix::Handler<live::Push> for Collector {
+        type Result = ();
+
+        fn handle(&mut self, push: live::Push, _: &mut Self::Context) {
+            self.0.push(push);
+        }
+    }
+
+    #[derive(actix::Message)]
+    #[rtype(result = "Vec<live::Push>")]
+    struct Drain;
+
+    impl actix::Handler<Drain> for Collector {
+        type Result = Vec<live::Push>;
+
+        fn handle(&mut self, _: Drain, _: &mut Self::Context) -> Vec<live::Push> {
+            std::mem::take(&mut self.0)
+        }
+    }
+
+    /// Everything pushed to a collector so far, once the hub has handled the
+    /// messages sent to it before.
+    async fn drain(
+        hub: &actix::Addr<live::ChangeHub>,
+        collector: &actix::Addr<Collector>,
+    ) -> Vec<live::Push> {
+        hub.send(live::Disconnect(usize::MAX)).await.unwrap();
+        collector.send(Drain).await.unwrap()
+    }
+
+    fn pushed_changes(pushes: &[live::Push]) -> Vec<&models::Change> {
+        pushes
+            .iter()
+            .map(|push| match push {
+                live::Push::Change(change) => change.as_ref(),
+                live::Push::Resync => panic!("unexpected resync"),
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn live_changes() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+        let hub = live::ChangeHub::default().start();
+        let collector = Collector::default().start();
+        hub.send(live::Connect {
+            session: collector.clone().recipient(),
+            after: None,
+        })
+        .await
+        .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .app_data(web::Data::new(hub.clone()))
+                .wrap(middleware::from_fn(live::publish_changes))
+                .service(add_device)
+                .service(put_device_state)
+                .service(add_device_readings)
+                .service(delete_device),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test socket",
+                "socket",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/state", device.id))
+            .set_json(models::TargetState {
+                state: true,
+                version: None,
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // failed requests push nothing
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/state", device.id))
+            .set_json(models::TargetState {
+                state: false,
+                version: Some(device.version + 100),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/readings", device.id))
+            .set_json(vec![models::NewReading::new(
+                models::Metric::Power,
+                12.6,
+                None,
+            )])
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let pushes = drain(&hub, &collector).await;
+        let changes = pushed_changes(&pushes);
+        let kinds: Vec<_> = changes.iter().map(|change| change.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                models::ChangeKind::Create,
+                models::ChangeKind::State,
+                models::ChangeKind::Readings,
+                models::ChangeKind::Delete,
+            ]
+        );
+        assert!(changes.windows(2).all(|pair| pair[0].id < pair[1].id));
+        for change in &changes {
+            assert_eq!(change.entity, "device");
+            assert_eq!(change.entity_id, device.id);
+            assert_eq!(change.house, house.id);
+            assert_eq!(change.rooms, std::slice::from_ref(&room.id));
+        }
+        assert_eq!(changes[1].data["state"], true);
+        assert_eq!(changes[2].data[0]["value"], 12.6);
+
+        // subscriptions match the house, the room or the device itself
+        let mut subscription = live::Subscription::default();
+        assert!(!subscription.matches(changes[0]));
+        subscription.rooms.insert(room.id.clone());
+        assert!(subscription.matches(changes[0]));
+        let subscription = live::Subscription {
+            devices: [device.id.clone()].into(),
+            ..Default::default()
+        };
+        assert!(subscription.matches(changes[0]));
+        let subscription = live::Subscription {
+            houses: [house.id.clone()].into(),
+            ..Default::default()
+        };
+        assert!(subscription.matches(changes[0]));
+
+        // a reconnecting session gets what it missed
+        let (first, last) = (changes[0].id, changes[3].id);
+        let replayed = Collector::default().start();
+        hub.do_send(live::Replay {
+            to: replayed.clone().recipient(),
+            after: first,
+        });
+        let pushes = drain(&hub, &replayed).await;
+        let ids: Vec<_> = pushed_changes(&pushes)
+            .iter()
+            .map(|change| change.id)
+            .collect();
+        assert_eq!(ids, [first + 1, first + 2, last]);
+
+        // or is told to reload when the id is unknown
+        hub.do_send(live::Replay {
+            to: replayed.clone().recipient(),
+            after: last + 1,
+        });
+        let pushes = drain(&hub, &replayed).await;
+        assert!(matches!(pushes[..], [live::Push::Resync]));
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn live_moves() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+        let hub = live::ChangeHub::default().start();
+        let collector = Collector::default().start();
+        hub.send(live::Connect {
+            session: collector.clone().recipient(),
+            after: None,
+        })
+        .await
+        .unwrap();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .app_data(web::Data::new(hub.clone()))
+                .wrap(middleware::from_fn(live::publish_changes))
+                .service(add_device)
+                .service(move_device)
+                .service(move_room),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let (other_house, other_room) = create_test_room(&pool);
+        let moving_room = actions::insert_new_room(
+            &mut pool.get().unwrap(),
+            "Moving room",
+            &house.id,
+            &models::Actor::new("test"),
+        )
+        .unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new("Lamp", "lamp", "virtual", &room.id))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        drain(&hub, &collector).await;
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/room", device.id))
+            .set_json(models::MoveDevice {
+                room: other_room.id.clone(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let req = test::TestRequest::put()
+            .uri(&format!("/room/{}/house", moving_room.id))
+            .set_json(models::MoveRoom {
+                house: other_house.id.clone(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // subscribers of the house left behind learn about both moves
+        let pushes = drain(&hub, &collector).await;
+        let changes = pushed_changes(&pushes);
+        assert_eq!(changes.len(), 2);
+        let old_house = live::Subscription {
+            houses: [house.id.clone()].into(),
+            ..Default::default()
+        };
+        let new_house = live::Subscription {
+            houses: [other_house.id.clone()].into(),
+            ..Default::default()
+        };
+        for change in changes {
+            assert_eq!(change.house, other_house.id);
+            assert_eq!(change.previous_house.as_ref(), Some(&house.id));
+            assert!(old_house.matches(change));
+            assert!(new_house.matches(change));
+        }
+
+        remove_test_house(&pool, &house.id);
+        remove_test_house(&pool, &other_house.id);
+    }
+
+    #[actix_web::test]
+    async fn live_buffer() {
+        let change = models::Change {
+            id: 0,
+            kind: models::ChangeKind::Update,
+            entity: String::from("house"),
+            entity_id: Uuid::nil().to_string(),
+            house: Uuid::nil().to_string(),
+            previous_house: None,
+            rooms: Vec::new(),
+            at: 0,
+            data: serde_json::Value::Null,
+        };
+        let hub = live::ChangeHub::new(2).start();
+        hub.do_send(live::Publish(vec![change.clone(), change.clone(), change]));
+        let collector = Collector::default().start();
+
+        // the first change has been dropped from the buffer
+        hub.do_send(live::Replay {
+            to: collector.clone().recipient(),
+            after: 0,
+        });
+        let pushes = drain(&hub, &collector).await;
+        assert!(matches!(pushes[..], [live::Push::Resync]));
+
+        hub.do_send(live::Replay {
+            to: collector.clone().recipient(),
+            after: 1,
+        });
+        let pushes = drain(&hub, &collector).await;
+        let ids: Vec<_> = pushed_changes(&pushes)
+            .iter()
+            .map(|change| change.id)
+            .collect();
+        assert_eq!(ids, [2, 3]);
+
+        hub.do_send(live::Replay {
+            to: collector.clone().recipient(),
+            after: 3,
+        });
+        assert!(drain(&hub, &collector).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn live_websocket_handshake() {
+        let hub = live::ChangeHub::default().start();
+        let app =
+            test::init_service(App::new().app_data(web::Data::new(hub)).service(get_ws)).await;
+
+        let req = test::TestRequest::get()
+            .uri("/ws")
+            .insert_header((header::UPGRADE, "websocket"))
+            .insert_header((header::CONNECTION, "upgrade"))
+            .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+            .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            res.headers().get(header::SEC_WEBSOCKET_ACCEPT).unwrap(),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+
+        // plain requests are not upgraded
+        let req = test::TestRequest::get().uri("/ws").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    /// Frames a WebSocket client sends, fed to a session under test.
+    struct ClientFrames(tokio::sync::mpsc::UnboundedReceiver<web::Bytes>);
+
+    impl actix::prelude::Stream for ClientFrames {
+        type Item = Result<web::Bytes, actix_web::error::PayloadError>;
+
+        fn poll_next(
+            mut self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<Option<Self::Item>> {
+            self.0.poll_recv(cx).map(|frame| frame.map(Ok))
+        }
+    }
+
+    /// Masked text frame, as clients send them.
+    fn client_text_frame(text: &str) -> web::Bytes {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0x81];
+        match text.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+        web::Bytes::from(frame)
+    }
+
+    /// Next text message a session sent, `None` when none arrives in time.
+    async fn next_server_text<S>(
+        frames: &mut std::pin::Pin<Box<S>>,
+        buf: &mut Vec<u8>,
+        wait: std::time::Duration,
+    ) -> Option<String>
+    where
+        S: actix::prelude::Stream<Item = Result<web::Bytes, actix_web::Error>>,
+    {
+        loop {
+            // server frames are unmasked: opcode, length, payload
+            if buf.len() >= 2 {
+                let (start, len) = match buf[1] & 0x7f {
+                    126 if buf.len() >= 4 => (4, usize::from(u16::from_be_bytes([buf[2], buf[3]]))),
+                    126 => (usize::MAX, 0),
+                    len => (2, usize::from(len)),
+                };
+                if start != usize::MAX && buf.len() >= start + len {
+                    let opcode = buf[0] & 0x0f;
+                    let payload: Vec<u8> = buf.drain(..start + len).skip(start).collect();
+                    if opcode == 1 {
+                        return Some(String::from_utf8(payload).unwrap());
+                    }
+                    continue;
+                }
+            }
+
+            let chunk = actix_web::rt::time::timeout(
+                wait,
+                std::future::poll_fn(|cx| frames.as_mut().poll_next(cx)),
+            )
+            .await
+            .ok()??;
+            buf.extend_from_slice(&chunk.unwrap());
+        }
+    }
+
+    #[actix_web::test]
+    async fn live_websocket_resume() {
+        let house = Uuid::new_v4();
+        let change = models::Change {
+            id: 0,
+            kind: models::ChangeKind::Update,
+            entity: String::from("house"),
+            entity_id: house.to_string(),
+            house: house.to_string(),
+            previous_house: None,
+            rooms: Vec::new(),
+            at: 0,
+            data: serde_json::Value::Null,
+        };
+        let hub = live::ChangeHub::default().start();
+        hub.do_send(live::Publish(vec![change.clone(), change.clone()]));
+
+        let (client, frames) = tokio::sync::mpsc::unbounded_channel();
+        let mut server = Box::pin(actix_web_actors::ws::WebsocketContext::create(
+            live::WsSession::new(hub.clone()),
+            ClientFrames(frames),
+        ));
+        client
+            .send(client_text_frame(&format!(
+                r#"{{"type": "subscribe", "houses": ["{house}"], "last_event_id": 1}}"#
+            )))
+            .unwrap();
+        // published while the session resumes; replayed or pushed, but only once
+        hub.do_send(live::Publish(vec![change]));
+
+        let mut buf = Vec::new();
+        let wait = std::time::Duration::from_secs(5);
+        let mut received = Vec::new();
+        while let Some(text) = next_server_text(&mut server, &mut buf, wait).await {
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            received.push((
+                message["type"].as_str().unwrap().to_owned(),
+                message["id"].as_u64(),
+            ));
+            if received.len() == 3 {
+                break;
+            }
+        }
+        assert_eq!(
+            received,
+            [
+                (String::from("subscribed"), None),
+                (String::from("change"), Some(2)),
+                (String::from("change"), Some(3)),
+            ]
+        );
+
+        // a later subscription can't resume again
+        client
+            .send(client_text_frame(
+                r#"{"type": "subscribe", "last_event_id": 0}"#,
+            ))
+            .unwrap();
+        let mut types = Vec::new();
+        while let Some(text) =
+            next_server_text(&mut server, &mut buf, std::time::Duration::from_millis(200)).await
+        {
+            let message: serde_json::Value = serde_json::from_str(&text).unwrap();
+            types.push(message["type"].as_str().unwrap().to_owned());
+        }
+        assert_eq!(types, ["subscribed", "error"]);
+    }
+
+    /// Next chunk of a streamed body, as text.
+    async fn next_chunk<B: actix_web::body::MessageBody>(
+        body: &mut std::pin::Pin<Box<B>>,
+    ) -> String {
+        let chunk = actix_web::rt::time::timeout(
+            std::time::Duration::from_secs(5),
+            std::future::poll_fn(|cx| body.as_mut().poll_next(cx)),
+        )
+        .await
+        .expect("no event streamed in time")
+        .and_then(Result::ok)
+        .expect("event stream ended");
+        String::from_utf8(chunk.to_vec()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn event_stream() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+        let hub = live::ChangeHub::default().start();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .app_data(web::Data::new(hub.clone()))
+                .wrap(middleware::from_fn(live::publish_changes))
+                .service(add_device)
+                .service(put_device_state)
+                .service(get_event_stream)
+                .service(get_event),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let (other_house, other_room) = create_test_room(&pool);
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test socket",
+                "socket",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        let toggle = |state| {
+            test::TestRequest::put()
+                .uri(&format!("/device/{}/state", device.id))
+                .set_json(models::TargetState {
+                    state,
+                    version: None,
+                })
+                .to_request()
+        };
+        let res = test::call_service(&app, toggle(true)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // a client resuming from before the creation gets both changes replayed
+        let req = test::TestRequest::get()
+            .uri(&format!("/events/stream?house={}", house.id))
+            .insert_header(("Last-Event-ID", "0"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/event-stream"
+        );
+        let mut body = Box::pin(res.into_body());
+        assert_eq!(next_chunk(&mut body).await, "retry: 3000\n\n");
+        let created = next_chunk(&mut body).await;
+        assert!(created.starts_with("id: 1\nevent: create\ndata: {"));
+        assert!(created.ends_with("}\n\n"));
+        let data: models::Change =
+            serde_json::from_str(created.lines().nth(2).unwrap().trim_start_matches("data: "))
+                .unwrap();
+        assert_eq!(data.entity_id, device.id);
+        assert!(next_chunk(&mut body)
+            .await
+            .starts_with("id: 2\nevent: state\n"));
+
+        // changes in other houses are left out of the stream
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Other socket",
+                "socket",
+                "192.168.0.2",
+                &other_room.id,
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let res = test::call_service(&app, toggle(false)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(next_chunk(&mut body)
+            .await
+            .starts_with("id: 4\nevent: state\n"));
+
+        // an id the server no longer knows asks the client to reload
+        let req = test::TestRequest::get()
+            .uri("/events/stream")
+            .insert_header(("Last-Event-ID", "100"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let mut body = Box::pin(res.into_body());
+        next_chunk(&mut body).await;
+        assert_eq!(next_chunk(&mut body).await, "event: resync\ndata: {}\n\n");
+
+        let req = test::TestRequest::get()
+            .uri("/events/stream?house=garage")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // a client which stops reading is dropped once its backlog is full
+        let mut stalled = Box::pin(live::event_stream(hub.clone(), None, None));
+        // let the session connect to the hub before the changes are published
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(50)).await;
+        let flood = 2 * live::BUFFER_SIZE;
+        let change = models::Change::readings(&device, &house.id, &[]);
+        hub.send(live::Publish(vec![change; flood])).await.unwrap();
+        let mut streamed = 0;
+        while actix_web::rt::time::timeout(
+            std::time::Duration::from_secs(5),
+            std::future::poll_fn(|cx| {
+                actix_web::body::MessageBody::poll_next(stalled.as_mut(), cx)
+            }),
+        )
+        .await
+        .expect("event stream of a dropped client didn't end")
+        .is_some()
+        {
+            streamed += 1;
+        }
+        assert!((live::BUFFER_SIZE..flood).contains(&streamed));
+
+        remove_test_house(&pool, &house.id);
+        remove_test_house(&pool, &other_house.id);
+    }
+
+    /// Stands in for the hardware at one address, remembering what it was told.
+    struct FakeDriver {
+        address: String,
+        offline: std::sync::atomic::AtomicBool,
+        calls: std::sync::Mutex<Vec<bool>>,
+        /// When set, the next switch changes the device behind the request's back.
+        interfere: std::sync::Mutex<Option<DbPool>>,
+    }
+
+    impl drivers::DeviceDriver for FakeDriver {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn connect(&self, _: &models::Device) -> Result<(), drivers::DriverError> {
+            if self.offline.load(std::sync::atomic::Ordering::SeqCst) {
+                return Err(drivers::DriverError(String::from("connection refused")));
+            }
+            Ok(())
+        }
+
+        fn set_state(&self, device: &models::Device, on: bool) -> Result<(), drivers::DriverError> {
+            self.connect(device)?;
+            self.calls.lock().unwrap().push(on);
+            if let Some(pool) = self.interfere.lock().unwrap().take() {
+                use crate::schema::devices::dsl::*;
+
+                diesel::update(devices.find(&device.id))
+                    .set(version.eq(version + 1))
+                    .execute(&mut pool.get().unwrap())
+                    .unwrap();
+            }
+            Ok(())
+        }
+
+        fn read_value(
+            &self,
+            device: &models::Device,
+        ) -> Result<Vec<models::NewReading>, drivers::DriverError> {
+            // other tests' sockets are polled as well; leave them alone
+            if device.address.as_ref() != Some(&self.address) {
+                return Ok(Vec::new());
+            }
+            self.connect(device)?;
+            Ok(vec![models::NewReading::new(
+                models::Metric::Power,
+                42.0,
+                None,
+            )])
+        }
+    }
+
+    #[actix_web::test]
+    async fn device_drivers() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+        let fake = std::sync::Arc::new(FakeDriver {
+            address: format!("fake-{}", Uuid::new_v4()),
+            offline: Default::default(),
+            calls: Default::default(),
+            interfere: Default::default(),
+        });
+        let drivers = drivers::Drivers::default().with(models::DeviceKind::Socket, fake.clone());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers.clone()))
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(get_device)
+                .service(patch_device)
+                .service(put_device_state)
+                .service(get_device_health)
+                .service(get_device_readings),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let mut devices = Vec::new();
+        for (name, kind, address) in [
+            ("Socket", "socket", fake.address.as_str()),
+            ("Lamp", "lamp", fake.address.as_str()),
+            ("Spare socket", "socket", "virtual"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/device")
+                .set_json(models::NewDevice::new(name, kind, address, &room.id))
+                .to_request();
+            let device: models::Device = test::call_and_read_body_json(&app, req).await;
+            devices.push(device);
+        }
+        let [socket, lamp, spare] = &devices[..] else {
+            unreachable!()
+        };
+        let set_state = |device: &models::Device, state| {
+            test::TestRequest::put()
+                .uri(&format!("/device/{}/state", device.id))
+                .set_json(models::TargetState {
+                    state,
+                    version: None,
+                })
+                .to_request()
+        };
+
+        // sockets are switched through their driver, other devices only in the database
+        for device in [socket, lamp, spare] {
+            let res = test::call_service(&app, set_state(device, true)).await;
+            assert_eq!(res.status(), StatusCode::OK);
+        }
+        assert_eq!(*fake.calls.lock().unwrap(), [true]);
+
+        let req = test::TestRequest::patch()
+            .uri(&format!("/device/{}", socket.id))
+            .set_json(models::DevicePatch {
+                state: Some(false),
+                ..Default::default()
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*fake.calls.lock().unwrap(), [true, false]);
+
+        // a device which can't be reached keeps its state
+        fake.offline
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        let res = test::call_service(&app, set_state(socket, true)).await;
+        assert_eq!(res.status(), StatusCode::BAD_GATEWAY);
+        let body: error::ErrorBody = test::read_body_json(res).await;
+        assert_eq!(body.code, "device_unavailable");
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}", socket.id))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert!(!device.state);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/health", socket.id))
+            .to_request();
+        let health: models::DeviceHealth = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(health.driver, "fake");
+        assert_eq!(health.status, models::HealthStatus::Offline);
+        assert_eq!(health.error.as_deref(), Some("connection refused"));
+
+        fake.offline
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+        for (device, driver, status) in [
+            (socket, "fake", models::HealthStatus::Online),
+            (lamp, "virtual", models::HealthStatus::Virtual),
+            (spare, "virtual", models::HealthStatus::Virtual),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/device/{}/health", device.id))
+                .to_request();
+            let health: models::DeviceHealth = test::call_and_read_body_json(&app, req).await;
+            assert_eq!((health.driver.as_str(), health.status), (driver, status));
+        }
+
+        // readings are polled from the device
+        let mut conn = pool.get().unwrap();
+        let changes = drivers::poll_devices(&mut conn, &drivers).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, models::ChangeKind::Readings);
+        assert_eq!(changes[0].entity_id, socket.id);
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/readings", socket.id))
+            .to_request();
+        let readings: Vec<models::Reading> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].value, 42.0);
+
+        // a switch which loses to a concurrent change is undone
+        let get_socket = || {
+            test::TestRequest::get()
+                .uri(&format!("/device/{}", socket.id))
+                .to_request()
+        };
+        let before: models::Device = test::call_and_read_body_json(&app, get_socket()).await;
+        assert!(!before.state);
+        fake.calls.lock().unwrap().clear();
+        *fake.interfere.lock().unwrap() = Some(pool.clone());
+        let res = test::call_service(&app, set_state(socket, true)).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(*fake.calls.lock().unwrap(), [true, false]);
+
+        let before: models::Device = test::call_and_read_body_json(&app, get_socket()).await;
+        assert!(!before.state);
+        fake.calls.lock().unwrap().clear();
+        *fake.interfere.lock().unwrap() = Some(pool.clone());
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/state", socket.id))
+            .insert_header((header::IF_MATCH, format!("\"{0}\"", before.version)))
+            .set_json(models::TargetState {
+                state: true,
+                version: None,
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(*fake.calls.lock().unwrap(), [true, false]);
+
+        fake.calls.lock().unwrap().clear();
+        *fake.interfere.lock().unwrap() = Some(pool.clone());
+        let req = test::TestRequest::patch()
+            .uri(&format!("/device/{}", socket.id))
+            .set_json(models::DevicePatch {
+                state: Some(true),
+                ..Default::default()
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        assert_eq!(*fake.calls.lock().unwrap(), [true, false]);
+        let after: models::Device = test::call_and_read_body_json(&app, get_socket()).await;
+        assert!(!after.state);
+        assert_eq!(after.version, before.version + 2);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/health", Uuid::nil()))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn udp_thermometer() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+        let hub = live::ChangeHub::default().start();
+        let collector = Collector::default().start();
+        hub.send(live::Connect {
+            session: collector.clone().recipient(),
+            after: None,
+        })
+        .await
+        .unwrap();
+        let counters = std::sync::Arc::new(udp::Counters::default());
+
+        let socket = actix_web::rt::net::UdpSocket::bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let server = socket.local_addr().unwrap();
+        actix_web::rt::spawn(udp::listen(
+            socket,
+            pool.clone(),
+            counters.clone(),
+            hub.clone(),
+        ));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::from(counters.clone()))
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(get_device_var)
+                .service(get_device_readings)
+                .service(get_device_history)
+                .service(get_metrics),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let mut devices = Vec::new();
+        for (name, kind) in [("Thermometer", "thermometer"), ("Lamp", "lamp")] {
+            let req = test::TestRequest::post()
+                .uri("/device")
+                .set_json(models::NewDevice::new(name, kind, "virtual", &room.id))
+                .to_request();
+            let device: models::Device = test::call_and_read_body_json(&app, req).await;
+            devices.push(device);
+        }
+        let [thermometer, lamp] = &devices[..] else {
+            unreachable!()
+        };
+        let thermometer_uid = Uuid::parse_str(&thermometer.id).unwrap();
+
+        let binary = |device: Uuid, celsius: f32| {
+            let mut datagram = device.as_bytes().to_vec();
+            datagram.extend_from_slice(&celsius.to_be_bytes());
+            datagram
+        };
+        let client = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        for (sent, datagram) in [
+            binary(thermometer_uid, 19.5),
+            format!(
+                r#"{{"device": "{0}", "temperature": 21.4}}"#,
+                thermometer.id
+            )
+            .into_bytes(),
+            b"hello".to_vec(),
+            binary(Uuid::new_v4(), 20.0),
+            format!(r#"{{"device": "{0}", "temperature": 20}}"#, lamp.id).into_bytes(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            client.send_to(&datagram, server).unwrap();
+            // wait until the datagram is handled, so readings arrive in order
+            let handled = |metrics: models::UdpMetrics| {
+                metrics.accepted
+                    + metrics.malformed
+                    + metrics.unknown_device
+                    + metrics.rejected
+                    + metrics.failed
+            };
+            actix_web::rt::time::timeout(std::time::Duration::from_secs(5), async {
+                while handled(counters.snapshot()) <= sent as u64 {
+                    actix_web::rt::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("datagram wasn't handled in time");
+        }
+
+        let req = test::TestRequest::get().uri("/metrics").to_request();
+        let metrics: models::Metrics = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(metrics.udp.received, 5);
+        assert_eq!(metrics.udp.accepted, 2);
+        assert_eq!(metrics.udp.malformed, 1);
+        assert_eq!(metrics.udp.unknown_device, 1);
+        assert_eq!(metrics.udp.rejected, 1);
+        assert_eq!(metrics.udp.failed, 0);
+
+        // the latest temperature is the value of the thermometer
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/readings", thermometer.id))
+            .to_request();
+        let readings: Vec<models::Reading> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].metric, models::Metric::Temperature);
+        assert_eq!(readings[0].value, 21.4);
+        assert_eq!(readings[0].unit, "°C");
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/var", thermometer.id))
+            .to_request();
+        let variable: i32 = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(variable, 21);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}/history", thermometer.id))
+            .to_request();
+        let history: models::History = test::call_and_read_body_json(&app, req).await;
+        let samples: i64 = history.points.iter().map(|point| point.samples).sum();
+        assert_eq!(samples, 2);
+
+        // accepted readings are pushed to live subscribers
+        let pushes = drain(&hub, &collector).await;
+        let changes = pushed_changes(&pushes);
+        assert_eq!(changes.len(), 2);
+        assert!(changes
+            .iter()
+            .all(|change| change.entity_id == thermometer.id));
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn trash_and_restore() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .wrap(middleware::Logger::default())
+                .service(get_device)
+                .service(add_device)
+                .service(delete_device)
+                .service(get_house)
+                .service(delete_house)
+                .service(get_trash)
+                .service(restore_house)
+                .service(restore_device),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Test socket",
+                "socket",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/house/{}", house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // deleted entities are hidden but listed in the trash
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get().uri("/trash").to_request();
+        let trash: models::Trash = test::call_and_read_body_json(&app, req).await;
+        assert!(trash.houses.iter().any(|other| other.id == house.id));
+        assert!(trash.rooms.iter().any(|other| other.id == room.id));
+        assert!(trash
+            .devices
+            .iter()
+            .any(|other| other.id == device.id && other.deleted_at.is_some()));
+
+        // children can't be restored into a deleted parent
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/restore", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        // restoring the house brings back its rooms and devices under their UUIDs
+        let req = test::TestRequest::post()
+            .uri(&format!("/house/{}/restore", house.id))
+            .to_request();
+        let restored: models::HouseTree = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(restored.house.id, house.id);
+        assert_eq!(restored.rooms[0].room.id, room.id);
+        assert_eq!(restored.rooms[0].devices[0].id, device.id);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(res.deleted_at, None);
+
+        // only deleted entities can be restored
+        let req = test::TestRequest::post()
+            .uri(&format!("/house/{}/restore", house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // the purge removes expired entities for good
+        let req = test::TestRequest::delete()
+            .uri(&format!("/device/{}", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let mut conn = pool.get().expect("couldn't get db connection from pool");
+        actions::purge_trash(&mut conn, actions::unix_now() + 1).expect("couldn't purge trash");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/device/{}/restore", device.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn list_paging() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .app_data(error::query_config())
+                .wrap(middleware::Logger::default())
+                .service(add_device)
+                .service(put_device_state)
+                .service(get_list_devices)
+                .service(get_devices_list)
+                .service(get_list_houses),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        let mut ids = Vec::new();
+        for (name, typ) in [
+            ("Lamp", "lamp"),
+            ("Socket A", "socket"),
+            ("Socket B", "socket"),
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/device")
+                .set_json(models::NewDevice::new(name, typ, "192.168.0.1", &room.id))
+                .to_request();
+            let device: models::Device = test::call_and_read_body_json(&app, req).await;
+            ids.push(device.id);
+        }
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/state", ids[0]))
+            .set_json(models::TargetState {
+                state: true,
+                version: None,
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // the first page links to the next one
+        let req = test::TestRequest::get()
+            .uri(&format!("/room/{}/list?limit=2&sort=-name", room.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let next = res
+            .headers()
+            .get("x-next-cursor")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let link = res
+            .headers()
+            .get("link")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_owned();
+        assert!(
+            link.contains(&format!("cursor={next}")),
+            "unexpected link: {link}"
+        );
+        assert!(link.ends_with("rel=\"next\""), "unexpected link: {link}");
+        // ids are listed with a trailing separator
+        let page: Vec<String> = test::read_body_json(res).await;
+        assert_eq!(
+            page.iter().map(|id| id.trim()).collect::<Vec<_>>(),
+            [&ids[2], &ids[1]]
+        );
+
+        let req = test::TestRequest::get()
+            .uri(&format!(
+                "/room/{}/list?limit=2&sort=-name&cursor={next}",
+                room.id
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert!(res.headers().get("link").is_none());
+        let page: Vec<String> = test::read_body_json(res).await;
+        assert_eq!(
+            page.iter().map(|id| id.trim()).collect::<Vec<_>>(),
+            [&ids[0]]
+        );
+
+        // filters combine
+        for (filter, expected) in [
+            ("type=socket", vec![&ids[1], &ids[2]]),
+            ("state=true", vec![&ids[0]]),
+            ("name=sOcKeT%20b", vec![&ids[2]]),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!("/devices-list?house={0}&{filter}", house.id))
+                .to_request();
+            let page: Vec<String> = test::call_and_read_body_json(&app, req).await;
+            assert_eq!(
+                page.iter().map(|id| id.trim()).collect::<Vec<_>>(),
+                expected,
+                "filter {filter}"
+            );
+        }
+
+        // filters and sort keys which don't apply are rejected
+        for uri in [
+            "/house-list?state=true",
+            "/house-list?sort=type",
+            "/devices-list?sort=colour",
+        ] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{uri}");
+        }
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn full_object_lists() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(error::query_config())
+                .wrap(middleware::Logger::default())
+                .service(web::scope("/api/v1").configure(configure_api)),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+
+        // an empty room is listed as an empty array
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/room/{}/devices", room.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let devices: Vec<models::Device> = test::read_body_json(res).await;
+        assert!(devices.is_empty());
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/device")
+            .set_json(models::NewDevice::new(
+                "Lamp",
+                "lamp",
+                "192.168.0.1",
+                &room.id,
+            ))
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/devices?house={}", house.id))
+            .to_request();
+        let devices: Vec<models::Device> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].id, device.id);
+        assert_eq!(devices[0].room, room.id);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/house/{}/rooms", house.id))
+            .to_request();
+        let rooms: Vec<models::Room> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].name, room.name);
+
+        // fields pick properties of every item
+        let req = test::TestRequest::get()
+            .uri(&format!("/api/v1/room/{}/devices?fields=id,state", room.id))
+            .to_request();
+        let devices: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            devices,
+            [serde_json::json!({ "id": device.id, "state": false })]
+        );
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/houses?fields=id,colour")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        remove_test_house(&pool, &house.id);
+    }
+
+    #[actix_web::test]
+    async fn openapi_document() {
+        let app =
+            test::init_service(App::new().service(web::scope("/api/v1").configure(configure_api)))
+                .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/openapi.json")
+            .to_request();
+        let doc: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        assert_eq!(doc["servers"][0]["url"], "/api/v1");
+        for path in [
+            "/devices",
+            "/device/{device_uid}",
+            "/house/{house_uid}/rooms",
+        ] {
+            assert!(doc["paths"][path].is_object(), "{path} is not documented");
+        }
+        assert!(doc["components"]["schemas"]["Device"].is_object());
+
+        let req = test::TestRequest::get().uri("/api/v1/docs").to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn rename_and_move() {
+        dotenvy::dotenv().ok();
+        env_logger::try_init_from_env(env_logger::Env::new().default_filter_or("info")).ok();
+
+        let pool = initialize_db_pool();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(drivers::Drivers::default()))
+                .wrap(middleware::Logger::default())
+                .service(add_house)
+                .service(add_room)
+                .service(add_device)
+                .service(rename_house)
+                .service(rename_room)
+                .service(rename_device)
+                .service(move_room)
+                .service(move_device),
+        )
+        .await;
+
+        let (house, room) = create_test_room(&pool);
+        let (other_house, other_room) = create_test_room(&pool);
+
+        let mut devices = Vec::new();
+        for name in ["First lamp", "Second lamp"] {
+            let req = test::TestRequest::post()
+                .uri("/device")
+                .set_json(models::NewDevice::new(
+                    name,
+                    "lamp",
+                    "192.168.0.1",
+                    &room.id,
+                ))
+                .to_request();
+            let device: models::Device = test::call_and_read_body_json(&app, req).await;
+            devices.push(device);
+        }
+
+        // names are unique within a room
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(
+                "Second lamp",
+                "lamp",
+                "192.168.0.2",
+                &room.id,
+            ))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::post()
+            .uri("/room")
+            .set_json(models::NewRoom::new(room.name.clone(), &house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::post()
+            .uri("/house")
+            .set_json(models::NewHouse::new(house.name.clone()))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        // and can't be blank
+        let req = test::TestRequest::post()
+            .uri("/device")
+            .set_json(models::NewDevice::new(" ", "lamp", "192.168.0.2", &room.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/room")
+            .set_json(models::NewRoom::new("", &house.id))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::post()
+            .uri("/house")
+            .set_json(models::NewHouse::new("\t"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/name", devices[0].id))
+            .set_json(models::Rename {
+                name: "Second lamp".to_owned(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/name", devices[0].id))
+            .set_json(models::Rename {
+                name: "Ceiling lamp".to_owned(),
+            })
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(device.name, "Ceiling lamp");
+        assert_eq!(device.id, devices[0].id);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/name", devices[0].id))
+            .set_json(models::Rename {
+                name: " ".to_owned(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        // devices keep their UID when moved
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/room", devices[1].id))
+            .set_json(models::MoveDevice {
+                room: other_room.id.clone(),
+            })
+            .to_request();
+        let device: models::Device = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(device.room, other_room.id);
+        assert_eq!(device.id, devices[1].id);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/room", devices[1].id))
+            .set_json(models::MoveDevice {
+                room: Uuid::nil().to_string(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/device/{}/room", Uuid::nil()))
+            .set_json(models::MoveDevice {
+                room: room.id.clone(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // both test rooms are called the same
+        let req = test::TestRequest::put()
+            .uri(&format!("/room/{}/house", other_room.id))
+            .set_json(models::MoveRoom {
+                house: house.id.clone(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/room/{}/name", other_room.id))
+            .set_json(models::Rename {
+                name: "Other room".to_owned(),
+            })
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/room/{}/house", other_room.id))
+            .set_json(models::MoveRoom {
+                house: house.id.clone(),
+            })
+            .to_request();
+        let moved: models::Room = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(moved.house, house.id);
+
+        let req = test::TestRequest::put()
+            .uri(&format!("/house/{}/name", other_house.id))
+            .set_json(models::Rename {
+                name: format!("Renamed house {}", other_house.id),
+            })
+            .to_request();
+        let renamed: models::House = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(renamed.id, other_house.id);
+
+        remove_test_house(&pool, &house.id);
+        remove_test_house(&pool, &other_house.id);
+    }
+
+    /// Creates a house with a single room for a test.
+    fn create_test_room(pool: &DbPool) -> (models::House, models::Room) {
+        let mut conn = pool.get().expect("couldn't get db connection from pool");
+        // tests run in parallel; unique names keep their houses from clashing
+        let house = actions::insert_new_house(
+            &mut conn,
+            &format!("Test house {}", Uuid::new_v4()),
+            &models::Actor::new("test"),
+        )
+        .expect("couldn't insert test house");
+        let room = actions::insert_new_room(
+            &mut conn,
+            "Test room",
+            &house.id,
+            &models::Actor::new("test"),
+        )
+        .expect("couldn't insert test room");
+
+        (house, room)
+    }
+
+    /// Removes a test house together with everything created inside it.
+    fn remove_test_house(pool: &DbPool, house_uid: &str) {
+        use crate::schema::{devices, events, houses, reading_history, readings, rooms};
+
+        let mut conn = pool.get().expect("couldn't get db connection from pool");
+        let room_ids = rooms::table
+            .filter(rooms::house.eq(house_uid.to_owned()))
+            .select(rooms::id)
+            .load::<String>(&mut conn)
+            .expect("couldn't list test rooms");
+        let device_ids = devices::table
+            .filter(devices::room.eq_any(&room_ids))
+            .select(devices::id)
+            .load::<String>(&mut conn)
+            .expect("couldn't list test devices");
+
+        diesel::delete(readings::table.filter(readings::device.eq_any(&device_ids)))
+            .execute(&mut conn)
+            .expect("couldn't delete test readings from table");
+        diesel::delete(reading_history::table.filter(reading_history::device.eq_any(&device_ids)))
+            .execute(&mut conn)
+            .expect("couldn't delete test history from table");
+        diesel::delete(devices::table.filter(devices::id.eq_any(&device_ids)))
+            .execute(&mut conn)
+            .expect("couldn't delete test devices from table");
+        diesel::delete(rooms::table.filter(rooms::id.eq_any(&room_ids)))
+            .execute(&mut conn)
+            .expect("couldn't delete test rooms from table");
+        diesel::delete(houses::table.filter(houses::id.eq(house_uid.to_owned())))
+            .execute(&mut conn)
+            .expect("couldn't delete test house from table");
+        diesel::delete(events::table.filter(events::house.eq(house_uid.to_owned())))
+            .execute(&mut conn)
+            .expect("couldn't delete test events from table");
     }
 }
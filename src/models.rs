@@ -1,25 +1,311 @@
-use crate::schema::{devices, houses, rooms};
+use crate::schema::{devices, events, houses, reading_history, readings, rooms, state_changes};
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
 use diesel::prelude::*;
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::{Sqlite, SqliteValue};
 use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::{fmt, str::FromStr};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
 
+/// Entity which can be listed and reported on.
 pub trait Item {
+    /// Label of the entity kind, e.g. `device`; reports look up its plural
+    /// under the `kind_{KIND}` message key.
+    const KIND: &'static str;
+    /// Properties which can be picked with `fields=` on the list endpoints.
+    const FIELDS: &'static [&'static str];
+
     fn name(&self) -> String;
     fn id(&self) -> String;
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+/// Kind of a smart device, stored in the `type` column of `devices`.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    Socket,
+    Thermometer,
+    Lamp,
+    Switch,
+    Dimmer,
+    Sensor,
+}
+
+/// Feature a device kind supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    Switchable,
+    Dimmable,
+    PowerMetering,
+    Temperature,
+    Humidity,
+    Battery,
+}
+
+impl DeviceKind {
+    pub const ALL: [DeviceKind; 6] = [
+        DeviceKind::Socket,
+        DeviceKind::Thermometer,
+        DeviceKind::Lamp,
+        DeviceKind::Switch,
+        DeviceKind::Dimmer,
+        DeviceKind::Sensor,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceKind::Socket => "socket",
+            DeviceKind::Thermometer => "thermometer",
+            DeviceKind::Lamp => "lamp",
+            DeviceKind::Switch => "switch",
+            DeviceKind::Dimmer => "dimmer",
+            DeviceKind::Sensor => "sensor",
+        }
+    }
+
+    pub fn capabilities(&self) -> &'static [Capability] {
+        use Capability::*;
+
+        match self {
+            DeviceKind::Socket => &[Switchable, PowerMetering],
+            DeviceKind::Thermometer => &[Temperature],
+            DeviceKind::Lamp => &[Switchable],
+            DeviceKind::Switch => &[Switchable],
+            DeviceKind::Dimmer => &[Switchable, Dimmable],
+            DeviceKind::Sensor => &[Temperature, Humidity, Battery],
+        }
+    }
+
+    /// Metrics a device of this kind may report, derived from its capabilities.
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.capabilities()
+            .iter()
+            .flat_map(|capability| match capability {
+                Capability::Switchable => &[][..],
+                Capability::Dimmable => &[Metric::Brightness][..],
+                Capability::PowerMetering => &[Metric::Power, Metric::Energy][..],
+                Capability::Temperature => &[Metric::Temperature][..],
+                Capability::Humidity => &[Metric::Humidity][..],
+                Capability::Battery => &[Metric::Battery][..],
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Metric mirrored into the legacy `variable` column, if any.
+    pub fn primary_metric(&self) -> Option<Metric> {
+        self.metrics().first().copied()
+    }
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownDeviceKind(pub String);
+
+impl fmt::Display for UnknownDeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown device type: {0}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownDeviceKind {}
+
+impl FromStr for DeviceKind {
+    type Err = UnknownDeviceKind;
+
+    /// Accepts the canonical names as well as the legacy spellings
+    /// ("SmartSocket", "Socket", "smart_thermometer", ...).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+
+        match normalized.strip_prefix("smart").unwrap_or(&normalized) {
+            "socket" | "plug" | "outlet" => Ok(DeviceKind::Socket),
+            "thermometer" => Ok(DeviceKind::Thermometer),
+            "lamp" | "light" | "bulb" => Ok(DeviceKind::Lamp),
+            "switch" => Ok(DeviceKind::Switch),
+            "dimmer" => Ok(DeviceKind::Dimmer),
+            "sensor" => Ok(DeviceKind::Sensor),
+            _ => Err(UnknownDeviceKind(s.to_owned())),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for DeviceKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for DeviceKind {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(value.parse()?)
+    }
+}
+
+/// Name of a value reported by a device.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    AsExpression,
+    FromSqlRow,
+    ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Temperature,
+    Power,
+    Energy,
+    Humidity,
+    Battery,
+    Brightness,
+}
+
+impl Metric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Temperature => "temperature",
+            Metric::Power => "power",
+            Metric::Energy => "energy",
+            Metric::Humidity => "humidity",
+            Metric::Battery => "battery",
+            Metric::Brightness => "brightness",
+        }
+    }
+
+    /// Accepted units; the first one is used when a reading omits its unit.
+    pub fn units(&self) -> &'static [&'static str] {
+        match self {
+            Metric::Temperature => &["°C", "°F", "K"],
+            Metric::Power => &["W", "kW"],
+            Metric::Energy => &["kWh", "Wh"],
+            Metric::Humidity | Metric::Battery | Metric::Brightness => &["%"],
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Metric {
+    type Err = InvalidReading;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "temperature" => Ok(Metric::Temperature),
+            "power" => Ok(Metric::Power),
+            "energy" => Ok(Metric::Energy),
+            "humidity" => Ok(Metric::Humidity),
+            "battery" => Ok(Metric::Battery),
+            "brightness" => Ok(Metric::Brightness),
+            _ => Err(InvalidReading(format!("Unknown metric: {s}"))),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for Metric {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Metric {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(value.parse()?)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidReading(pub String);
+
+impl fmt::Display for InvalidReading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidReading {}
+
+/// Entry of the `/device-kinds` listing.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DeviceKindInfo {
+    pub kind: DeviceKind,
+    #[schema(value_type = Vec<Capability>)]
+    pub capabilities: &'static [Capability],
+}
+
+impl From<DeviceKind> for DeviceKindInfo {
+    fn from(kind: DeviceKind) -> Self {
+        Self {
+            kind,
+            capabilities: kind.capabilities(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, ToSchema)]
 #[diesel(table_name = devices)]
 pub struct Device {
     pub id: String,
     pub name: String,
-    pub type_: String,
+    pub type_: DeviceKind,
     pub address: Option<String>,
     pub state: bool,
     pub variable: i32,
     pub room: String,
+    /// Bumped on every change made through the API, except telemetry.
+    pub version: i32,
+    /// Unix timestamp of the deletion while the device is in the trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<i64>,
 }
 
 impl Item for Device {
+    const KIND: &'static str = "device";
+    const FIELDS: &'static [&'static str] = &[
+        "id", "name", "type_", "address", "state", "variable", "room", "version",
+    ];
+
     fn name(&self) -> String {
         String::from(&self.name)
     }
@@ -28,15 +314,80 @@ impl Item for Device {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+/// Latest value of one metric of a device.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, ToSchema)]
+#[diesel(table_name = readings)]
+pub struct Reading {
+    pub device: String,
+    pub metric: Metric,
+    pub value: f64,
+    pub unit: String,
+    pub updated_at: i64,
+}
+
+/// Timestamped reading kept for range queries.
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = reading_history)]
+pub struct HistorySample {
+    pub device: String,
+    pub metric: Metric,
+    pub value: f64,
+    pub unit: String,
+    pub recorded_at: i64,
+}
+
+/// Device switched on or off, kept to derive on-time.
+#[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = state_changes)]
+pub struct StateSample {
+    pub device: String,
+    pub state: bool,
+    pub changed_at: i64,
+}
+
+/// Downsampled readings within one `step` wide time bucket.
+#[derive(Debug, Clone, Serialize, Deserialize, QueryableByName, ToSchema)]
+pub struct HistoryBucket {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub time: i64,
+    #[diesel(sql_type = Text)]
+    pub unit: String,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    pub min: f64,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    pub max: f64,
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    pub avg: f64,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub samples: i64,
+}
+
+/// Readings of one metric of a device over a time range.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct History {
+    pub device: String,
+    pub metric: Metric,
+    pub from: i64,
+    pub to: i64,
+    pub step: i64,
+    pub points: Vec<HistoryBucket>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, ToSchema)]
 #[diesel(table_name = rooms)]
 pub struct Room {
     pub id: String,
     pub name: String,
     pub house: String,
+    /// Unix timestamp of the deletion while the room is in the trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<i64>,
 }
 
 impl Item for Room {
+    const KIND: &'static str = "room";
+    const FIELDS: &'static [&'static str] = &["id", "name", "house"];
+
     fn name(&self) -> String {
         String::from(&self.name)
     }
@@ -45,14 +396,20 @@ impl Item for Room {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, ToSchema)]
 #[diesel(table_name = houses)]
 pub struct House {
     pub id: String,
     pub name: String,
+    /// Unix timestamp of the deletion while the house is in the trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<i64>,
 }
 
 impl Item for House {
+    const KIND: &'static str = "house";
+    const FIELDS: &'static [&'static str] = &["id", "name"];
+
     fn name(&self) -> String {
         String::from(&self.name)
     }
@@ -61,7 +418,223 @@ impl Item for House {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Room together with its devices.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RoomTree {
+    #[serde(flatten)]
+    pub room: Room,
+    pub devices: Vec<Device>,
+}
+
+/// House together with its rooms and their devices.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HouseTree {
+    #[serde(flatten)]
+    pub house: House,
+    pub rooms: Vec<RoomTree>,
+}
+
+/// Report on a house: its rooms with their devices and latest readings.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct HouseReport {
+    pub house: House,
+    pub rooms: Vec<RoomReport>,
+    /// Unix timestamp of the report.
+    pub generated_at: i64,
+}
+
+/// Room of a [`HouseReport`].
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RoomReport {
+    #[serde(flatten)]
+    pub room: Room,
+    pub devices: Vec<DeviceReport>,
+}
+
+/// Device of a [`HouseReport`] with its latest readings.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeviceReport {
+    #[serde(flatten)]
+    pub device: Device,
+    pub readings: Vec<Reading>,
+}
+
+/// Representation of a house report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    Json,
+    Text,
+    Markdown,
+    Csv,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "application/json",
+            ReportFormat::Text => "text/plain; charset=utf-8",
+            ReportFormat::Markdown => "text/markdown; charset=utf-8",
+            ReportFormat::Csv => "text/csv; charset=utf-8",
+            ReportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    /// Extension of exported report files.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Text => "txt",
+            ReportFormat::Markdown => "md",
+            ReportFormat::Csv => "csv",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        [
+            ReportFormat::Json,
+            ReportFormat::Text,
+            ReportFormat::Markdown,
+            ReportFormat::Csv,
+            ReportFormat::Html,
+        ]
+        .into_iter()
+        .find(|format| format.extension() == extension)
+    }
+
+    /// Format matching a media type of an `Accept` header, if any.
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "*/*" => Some(ReportFormat::Json),
+            "text/plain" | "text/*" => Some(ReportFormat::Text),
+            "text/markdown" => Some(ReportFormat::Markdown),
+            "text/csv" => Some(ReportFormat::Csv),
+            "text/html" => Some(ReportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Query of `/report/{uid}`; takes precedence over the `Accept` and
+/// `Accept-Language` headers.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct ReportQuery {
+    pub format: Option<ReportFormat>,
+    /// Language of the report text, e.g. `en` or `ru`.
+    pub lang: Option<String>,
+}
+
+/// Energy used within a time window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EnergyUsage {
+    /// Energy in kilowatt-hours, from the energy meter or else integrated power samples.
+    pub energy_kwh: f64,
+    /// Highest power sample in watts.
+    pub peak_w: f64,
+    /// Time the device was switched on, in seconds.
+    pub on_time_secs: i64,
+}
+
+impl EnergyUsage {
+    /// Adds up energy and on-time; the peak is the highest single peak.
+    pub fn add(&mut self, other: &EnergyUsage) {
+        self.energy_kwh += other.energy_kwh;
+        self.peak_w = self.peak_w.max(other.peak_w);
+        self.on_time_secs += other.on_time_secs;
+    }
+}
+
+/// Socket of an [`EnergyReport`].
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeviceEnergy {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub usage: EnergyUsage,
+}
+
+/// Room of an [`EnergyReport`] with the total of its sockets.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct RoomEnergy {
+    pub id: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub usage: EnergyUsage,
+    pub devices: Vec<DeviceEnergy>,
+}
+
+/// Energy used by the sockets of a house within `[from, to)`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EnergyReport {
+    pub house: House,
+    pub from: i64,
+    pub to: i64,
+    #[serde(flatten)]
+    pub usage: EnergyUsage,
+    pub rooms: Vec<RoomEnergy>,
+}
+
+/// Query of `/house/{uid}/energy`; times are Unix timestamps in seconds.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct EnergyQuery {
+    /// Start of the window; a day before `to` by default.
+    pub from: Option<i64>,
+    /// End of the window; now by default.
+    pub to: Option<i64>,
+    pub format: Option<ReportFormat>,
+    pub lang: Option<String>,
+}
+
+/// Report file written by an export.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ExportFile {
+    /// File name, also used to download it from `/exports/{name}`.
+    pub name: String,
+    pub house: String,
+    pub format: ReportFormat,
+    /// Unix timestamp of the export in milliseconds.
+    pub created_at: i64,
+    pub size: u64,
+}
+
+/// Query of `POST /exports`; absent values fall back to the export configuration.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct ExportQuery {
+    /// House to export; every configured house by default.
+    pub house: Option<Uuid>,
+    pub format: Option<ReportFormat>,
+    pub lang: Option<String>,
+}
+
+/// Deleted entities which can still be restored.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Trash {
+    pub houses: Vec<House>,
+    pub rooms: Vec<Room>,
+    pub devices: Vec<Device>,
+}
+
+/// Number of dependent rows removed by a cascading delete.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CascadeCounts {
+    pub rooms: i64,
+    pub devices: i64,
+    pub readings: i64,
+    pub history: i64,
+}
+
+/// Entity removed, or to be removed by a dry run, with everything depending on it.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Removal<T> {
+    #[serde(flatten)]
+    pub tree: T,
+    pub counts: CascadeCounts,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct NewDevice {
     pub name: String,
     pub typ: String,
@@ -69,13 +642,669 @@ pub struct NewDevice {
     pub room: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Partial update of a device; absent fields are left untouched.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, AsChangeset, ToSchema)]
+#[diesel(table_name = devices)]
+pub struct DevicePatch {
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub room: Option<String>,
+    pub state: Option<bool>,
+    pub variable: Option<i32>,
+}
+
+impl DevicePatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.address.is_none()
+            && self.room.is_none()
+            && self.state.is_none()
+            && self.variable.is_none()
+    }
+}
+
+/// Partial update of a room; absent fields are left untouched.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, AsChangeset, ToSchema)]
+#[diesel(table_name = rooms)]
+pub struct RoomPatch {
+    pub name: Option<String>,
+    pub house: Option<String>,
+}
+
+impl RoomPatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.house.is_none()
+    }
+}
+
+/// Partial update of a house; absent fields are left untouched.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, AsChangeset, ToSchema)]
+#[diesel(table_name = houses)]
+pub struct HousePatch {
+    pub name: Option<String>,
+}
+
+impl HousePatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+    }
+}
+
+/// Body of `PUT /{house,room,device}/{uid}/name`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Rename {
+    pub name: String,
+}
+
+/// Body of `PUT /device/{uid}/room`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MoveDevice {
+    pub room: String,
+}
+
+/// Body of `PUT /room/{uid}/house`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MoveRoom {
+    pub house: String,
+}
+
+/// Body of `PUT /device/{uid}/state`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TargetState {
+    pub state: bool,
+    /// Only apply the change if the device is still at this version.
+    pub version: Option<i32>,
+}
+
+/// Outcome of `PUT /device/{uid}/state`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StateChange {
+    pub device: Device,
+    pub changed: bool,
+}
+
+/// Whether the hardware behind a device answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Online,
+    Offline,
+    /// The device only exists in the database.
+    Virtual,
+}
+
+/// Outcome of `GET /device/{uid}/health`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DeviceHealth {
+    pub device: String,
+    /// Driver the device is reached through, e.g. `virtual`.
+    pub driver: String,
+    pub status: HealthStatus,
+    /// Why the device is offline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub checked_at: i64,
+}
+
+/// Counts of the datagrams received by the UDP thermometer listener.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct UdpMetrics {
+    pub received: u64,
+    /// Stored as a reading of the device.
+    pub accepted: u64,
+    /// Neither a JSON nor a binary reading.
+    pub malformed: u64,
+    /// About a device that doesn't exist or is deleted.
+    pub unknown_device: u64,
+    /// About a device that doesn't report temperature, or with an invalid unit or value.
+    pub rejected: u64,
+    /// Not stored because of a server error, e.g. the database being unavailable.
+    pub failed: u64,
+}
+
+/// Outcome of `GET /metrics`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Metrics {
+    pub udp: UdpMetrics,
+}
+
+/// What happened to an entity in an [`Event`].
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema,
+)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum EventAction {
+    Create,
+    Update,
+    /// A device was switched on or off.
+    State,
+    Delete,
+    Restore,
+}
+
+impl EventAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventAction::Create => "create",
+            EventAction::Update => "update",
+            EventAction::State => "state",
+            EventAction::Delete => "delete",
+            EventAction::Restore => "restore",
+        }
+    }
+}
+
+impl fmt::Display for EventAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl ToSql<Text, Sqlite> for EventAction {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for EventAction {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "create" => Ok(EventAction::Create),
+            "update" => Ok(EventAction::Update),
+            "state" => Ok(EventAction::State),
+            "delete" => Ok(EventAction::Delete),
+            "restore" => Ok(EventAction::Restore),
+            other => Err(format!("Unknown event action: {other}").into()),
+        }
+    }
+}
+
+/// Entity as it was before or after an [`Event`], stored as JSON text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, AsExpression, FromSqlRow, ToSchema)]
+#[diesel(sql_type = Text)]
+#[serde(transparent)]
+#[schema(value_type = Object)]
+pub struct Snapshot(pub serde_json::Value);
+
+impl ToSql<Text, Sqlite> for Snapshot {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.0.to_string());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Snapshot {
+    fn from_sql(bytes: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(Snapshot(serde_json::from_str(&value)?))
+    }
+}
+
+/// Entry of the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, ToSchema)]
+pub struct Event {
+    pub id: i32,
+    /// Unix timestamp of the change.
+    pub at: i64,
+    pub action: EventAction,
+    /// `device`, `room` or `house`.
+    pub entity: String,
+    pub entity_id: String,
+    /// House the entity belongs to.
+    pub house: String,
+    /// `X-Client-Id` of the request, or else the address of the client.
+    pub client: String,
+    pub old_value: Option<Snapshot>,
+    pub new_value: Option<Snapshot>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = events)]
+pub struct NewEvent {
+    pub at: i64,
+    pub action: EventAction,
+    pub entity: &'static str,
+    pub entity_id: String,
+    pub house: String,
+    pub client: String,
+    pub old_value: Option<Snapshot>,
+    pub new_value: Option<Snapshot>,
+}
+
+/// What a [`Change`] pushed to live subscribers is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Create,
+    Update,
+    State,
+    Delete,
+    Restore,
+    /// New readings of a device.
+    Readings,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Create => "create",
+            ChangeKind::Update => "update",
+            ChangeKind::State => "state",
+            ChangeKind::Delete => "delete",
+            ChangeKind::Restore => "restore",
+            ChangeKind::Readings => "readings",
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<EventAction> for ChangeKind {
+    fn from(action: EventAction) -> Self {
+        match action {
+            EventAction::Create => ChangeKind::Create,
+            EventAction::Update => ChangeKind::Update,
+            EventAction::State => ChangeKind::State,
+            EventAction::Delete => ChangeKind::Delete,
+            EventAction::Restore => ChangeKind::Restore,
+        }
+    }
+}
+
+/// Change pushed to live subscribers.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Change {
+    /// Position in the live stream, assigned when the change is published.
+    pub id: u64,
+    pub kind: ChangeKind,
+    /// `device`, `room` or `house`.
+    pub entity: String,
+    pub entity_id: String,
+    pub house: String,
+    /// House a room or device was moved out of, when it left another house.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_house: Option<String>,
+    /// The room itself, or the rooms of a device before and after the change.
+    pub rooms: Vec<String>,
+    pub at: i64,
+    /// Entity after the change, or before it when deleted; the readings for `readings`.
+    #[schema(value_type = Object)]
+    pub data: serde_json::Value,
+}
+
+impl Change {
+    /// Whether the change concerns the house, including entities moved out of it.
+    pub fn in_house(&self, house: &str) -> bool {
+        self.house == house || self.previous_house.as_deref() == Some(house)
+    }
+
+    pub fn from_event(event: &Event) -> Self {
+        let snapshots = [&event.old_value, &event.new_value];
+        let mut rooms: Vec<String> = if event.entity == Room::KIND {
+            vec![event.entity_id.clone()]
+        } else {
+            snapshots
+                .iter()
+                .filter_map(|snapshot| snapshot.as_ref()?.0.get("room")?.as_str())
+                .map(String::from)
+                .collect()
+        };
+        rooms.dedup();
+        // a moved device's previous house is only known from its old room
+        let previous_house = (event.entity == Room::KIND)
+            .then(|| event.old_value.as_ref()?.0.get("house")?.as_str())
+            .flatten()
+            .filter(|house| *house != event.house)
+            .map(String::from);
+
+        Change {
+            id: 0,
+            kind: event.action.into(),
+            entity: event.entity.clone(),
+            entity_id: event.entity_id.clone(),
+            house: event.house.clone(),
+            previous_house,
+            rooms,
+            at: event.at,
+            data: event
+                .new_value
+                .as_ref()
+                .or(event.old_value.as_ref())
+                .map_or(serde_json::Value::Null, |snapshot| snapshot.0.clone()),
+        }
+    }
+
+    pub fn readings(device: &Device, house: &str, readings: &[Reading]) -> Self {
+        Change {
+            id: 0,
+            kind: ChangeKind::Readings,
+            entity: String::from(Device::KIND),
+            entity_id: device.id.clone(),
+            house: house.to_owned(),
+            previous_house: None,
+            rooms: vec![device.room.clone()],
+            at: readings
+                .iter()
+                .map(|reading| reading.updated_at)
+                .max()
+                .unwrap_or_default(),
+            data: serde_json::to_value(readings).unwrap_or_default(),
+        }
+    }
+}
+
+/// Client making a request, with the changes made on its behalf which are
+/// pushed to live subscribers once the request has succeeded.
+#[derive(Debug, Default)]
+pub struct Actor {
+    pub client: String,
+    changes: Mutex<Vec<Change>>,
+}
+
+impl Actor {
+    pub fn new(client: impl Into<String>) -> Self {
+        Actor {
+            client: client.into(),
+            changes: Mutex::default(),
+        }
+    }
+
+    pub fn record(&self, change: Change) {
+        if let Ok(mut changes) = self.changes.lock() {
+            changes.push(change);
+        }
+    }
+
+    pub fn take_changes(&self) -> Vec<Change> {
+        self.changes
+            .lock()
+            .map(|mut changes| std::mem::take(&mut *changes))
+            .unwrap_or_default()
+    }
+}
+
+/// Query of `/events`; events are listed oldest first.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct EventQuery {
+    pub limit: Option<i64>,
+    /// `X-Next-Cursor` of the previous page.
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+    /// `device`, `room` or `house`.
+    pub entity: Option<String>,
+    pub entity_id: Option<Uuid>,
+    pub house: Option<Uuid>,
+    pub action: Option<EventAction>,
+    pub client: Option<String>,
+    /// Unix timestamp of the earliest change.
+    pub from: Option<i64>,
+    /// Unix timestamp the changes happened before.
+    pub to: Option<i64>,
+}
+
+impl EventQuery {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(ListQuery::DEFAULT_LIMIT)
+            .clamp(1, ListQuery::MAX_LIMIT)
+    }
+}
+
+/// Query of `/events/stream`.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct StreamQuery {
+    /// Only stream the changes in this house.
+    pub house: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NewReading {
+    pub metric: Metric,
+    pub value: f64,
+    pub unit: Option<String>,
+    /// Unix timestamp of the measurement; defaults to the time it is received.
+    pub recorded_at: Option<i64>,
+}
+
+/// Query of `/device/{uid}/history`; times are Unix timestamps in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    pub metric: Option<Metric>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub step: Option<i64>,
+}
+
+/// Field a list is ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Id,
+    Name,
+    Type,
+    State,
+}
+
+/// Order of a list, written as a field name with an optional `-` prefix for
+/// descending order, e.g. `name` or `-state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            field: SortField::Name,
+            descending: false,
+        }
+    }
+}
+
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (descending, field) = match s.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, s),
+        };
+        let field = match field {
+            "id" => SortField::Id,
+            "name" => SortField::Name,
+            "type" => SortField::Type,
+            "state" => SortField::State,
+            _ => return Err(format!("Unknown sort key: {s}")),
+        };
+
+        Ok(Self { field, descending })
+    }
+}
+
+impl<'de> Deserialize<'de> for Sort {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Opaque position in a list, handed out as the next cursor of a page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cursor(pub i64);
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{0}", self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cursor {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match String::deserialize(deserializer)?.parse::<i64>() {
+            Ok(offset) if offset >= 0 => Ok(Cursor(offset)),
+            _ => Err(serde::de::Error::custom("Invalid cursor")),
+        }
+    }
+}
+
+/// Query of the list endpoints.
+///
+/// Filters which don't apply to the listed entity are rejected.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct ListQuery {
+    pub limit: Option<i64>,
+    /// `X-Next-Cursor` of the previous page.
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+    /// `id`, `name`, `type` or `state`, prefixed with `-` for descending order.
+    #[param(value_type = Option<String>)]
+    pub sort: Option<Sort>,
+    #[serde(rename = "type")]
+    pub kind: Option<DeviceKind>,
+    pub state: Option<bool>,
+    pub room: Option<String>,
+    pub house: Option<String>,
+    /// Substring of the name, matched case-insensitively.
+    pub name: Option<String>,
+}
+
+impl ListQuery {
+    /// Number of items on a page when no limit is requested.
+    pub const DEFAULT_LIMIT: i64 = 100;
+    /// Upper bound on the number of items on a page.
+    pub const MAX_LIMIT: i64 = 1000;
+
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .clamp(1, Self::MAX_LIMIT)
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.cursor.unwrap_or_default().0
+    }
+
+    /// `LIKE` pattern for the name filter.
+    pub fn name_pattern(&self) -> Option<String> {
+        self.name.as_ref().map(|name| {
+            let escaped = name
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{escaped}%")
+        })
+    }
+}
+
+/// One page of a list.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<Cursor>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from up to `limit + 1` loaded items; the extra one only
+    /// tells whether there is a next page.
+    pub fn new(mut items: Vec<T>, query: &ListQuery) -> Self {
+        let limit = query.limit() as usize;
+        let next_cursor = (items.len() > limit).then(|| {
+            items.truncate(limit);
+            Cursor(query.offset() + limit as i64)
+        });
+
+        Self { items, next_cursor }
+    }
+}
+
+/// Projection of the versioned list endpoints, e.g. `fields=id,name`.
+///
+/// Without it whole objects are returned.
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+pub struct Fields {
+    pub fields: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct UnknownField(pub String);
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown field: {0}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownField {}
+
+impl Fields {
+    /// Serializes the items, keeping only the requested properties.
+    pub fn project<T: Item + Serialize>(
+        &self,
+        items: Vec<T>,
+    ) -> Result<Vec<serde_json::Value>, UnknownField> {
+        let items = items.into_iter().map(|item| serde_json::json!(item));
+        let Some(fields) = &self.fields else {
+            return Ok(items.collect());
+        };
+
+        let fields: Vec<&str> = fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect();
+        if let Some(unknown) = fields.iter().find(|field| !T::FIELDS.contains(field)) {
+            return Err(UnknownField((*unknown).to_owned()));
+        }
+
+        Ok(items
+            .map(|mut item| {
+                if let Some(object) = item.as_object_mut() {
+                    object.retain(|key, _| fields.contains(&key.as_str()));
+                }
+                item
+            })
+            .collect())
+    }
+}
+
+/// Query of `DELETE /device/{uid}`, `DELETE /room/{uid}` and `DELETE /house/{uid}`.
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+pub struct DeleteOptions {
+    /// Whether children are removed too; otherwise a parent with children is kept.
+    #[serde(default = "default_cascade")]
+    pub cascade: bool,
+    /// Only report what would be removed.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl Default for DeleteOptions {
+    fn default() -> Self {
+        Self {
+            cascade: default_cascade(),
+            dry_run: false,
+        }
+    }
+}
+
+fn default_cascade() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct NewRoom {
     pub name: String,
     pub house: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct NewHouse {
     pub name: String,
 }
@@ -97,6 +1326,42 @@ impl NewDevice {
     }
 }
 
+impl NewReading {
+    #[cfg(test)] // only needed in tests
+    pub fn new(metric: Metric, value: f64, unit: Option<&str>) -> Self {
+        Self {
+            metric,
+            value,
+            unit: unit.map(String::from),
+            recorded_at: None,
+        }
+    }
+
+    /// Checks the reading against the device kind and resolves its unit.
+    pub fn validate(&self, kind: DeviceKind) -> Result<&str, InvalidReading> {
+        if !kind.metrics().contains(&self.metric) {
+            return Err(InvalidReading(format!(
+                "Device of type {kind} does not report {0}",
+                self.metric
+            )));
+        }
+        if !self.value.is_finite() {
+            return Err(InvalidReading(format!(
+                "Value of {0} must be a finite number",
+                self.metric
+            )));
+        }
+        match self.unit.as_deref() {
+            None => Ok(self.metric.units()[0]),
+            Some(unit) if self.metric.units().contains(&unit) => Ok(unit),
+            Some(unit) => Err(InvalidReading(format!(
+                "Unit {unit} is not valid for {0}",
+                self.metric
+            ))),
+        }
+    }
+}
+
 impl NewRoom {
     #[cfg(test)] // only needed in tests
     pub fn new(name: impl Into<String>, house: impl Into<String>) -> Self {
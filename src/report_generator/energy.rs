@@ -0,0 +1,220 @@
+use super::house::{csv_field, html_escape, markdown_cell};
+use super::Catalog;
+use crate::models::{EnergyReport, EnergyUsage, ReportFormat};
+use chrono::DateTime;
+use std::fmt::Write;
+
+/// Renders an energy report in the requested format, with phrases from the catalogue.
+///
+/// JSON and CSV are meant for machines and don't depend on the language.
+pub fn render(report: &EnergyReport, format: ReportFormat, catalog: &Catalog) -> String {
+    match format {
+        ReportFormat::Json => serde_json::to_string(report).unwrap_or_default(),
+        ReportFormat::Text => render_text(report, catalog),
+        ReportFormat::Markdown => render_markdown(report, catalog),
+        ReportFormat::Csv => render_csv(report),
+        ReportFormat::Html => render_html(report, catalog),
+    }
+}
+
+fn timestamp(secs: i64) -> String {
+    DateTime::from_timestamp(secs, 0)
+        .map(|at| at.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_else(|| secs.to_string())
+}
+
+fn title(report: &EnergyReport, catalog: &Catalog) -> String {
+    catalog.format("energy_report", &[("house", &report.house.name)])
+}
+
+fn period(report: &EnergyReport, catalog: &Catalog) -> String {
+    catalog.format(
+        "period",
+        &[
+            ("from", &timestamp(report.from)),
+            ("to", &timestamp(report.to)),
+        ],
+    )
+}
+
+/// On-time as `h:mm:ss`, which reads the same in every language.
+fn duration(secs: i64) -> String {
+    format!("{0}:{1:02}:{2:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Energy, peak and on-time cells of a row.
+fn usage_cells(usage: &EnergyUsage) -> [String; 3] {
+    [
+        format!("{0:.3}", usage.energy_kwh),
+        format!("{0:.1}", usage.peak_w),
+        duration(usage.on_time_secs),
+    ]
+}
+
+/// Usage in running text, e.g. `1.250 kWh, 1800.0 W, 0:45:00`.
+fn usage_line(usage: &EnergyUsage, catalog: &Catalog) -> String {
+    let [energy, peak, on_time] = usage_cells(usage);
+    format!(
+        "{energy} {0}, {peak} {1}, {on_time}",
+        catalog.text("unit_kwh"),
+        catalog.text("unit_w")
+    )
+}
+
+fn columns(catalog: &Catalog) -> [&str; 4] {
+    [
+        catalog.text("column_device"),
+        catalog.text("column_energy"),
+        catalog.text("column_peak"),
+        catalog.text("column_on_time"),
+    ]
+}
+
+fn render_text(report: &EnergyReport, catalog: &Catalog) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{0}", title(report, catalog));
+    let _ = writeln!(out, "{0}", period(report, catalog));
+    let _ = writeln!(
+        out,
+        "{0}: {1}",
+        catalog.text("total"),
+        usage_line(&report.usage, catalog)
+    );
+
+    for room in &report.rooms {
+        let _ = writeln!(
+            out,
+            "\n{0}: {1}",
+            catalog.format("room", &[("room", &room.name)]),
+            usage_line(&room.usage, catalog)
+        );
+        if room.devices.is_empty() {
+            let _ = writeln!(out, "  {0}", catalog.text("no_sockets"));
+        }
+        for device in &room.devices {
+            let _ = writeln!(
+                out,
+                "  - {0}: {1}",
+                device.name,
+                usage_line(&device.usage, catalog)
+            );
+        }
+    }
+
+    out
+}
+
+fn render_markdown(report: &EnergyReport, catalog: &Catalog) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {0}\n", markdown_cell(&title(report, catalog)));
+    let _ = writeln!(out, "{0}", period(report, catalog));
+
+    for room in &report.rooms {
+        let _ = writeln!(out, "\n## {0}\n", markdown_cell(&room.name));
+        if room.devices.is_empty() {
+            let _ = writeln!(out, "{0}.", catalog.text("no_sockets"));
+            continue;
+        }
+
+        let _ = writeln!(out, "| {0} |", columns(catalog).join(" | "));
+        let _ = writeln!(out, "|---|---:|---:|---:|");
+        for device in &room.devices {
+            let _ = writeln!(
+                out,
+                "| {0} | {1} |",
+                markdown_cell(&device.name),
+                usage_cells(&device.usage).join(" | ")
+            );
+        }
+        let _ = writeln!(
+            out,
+            "| **{0}** | {1} |",
+            catalog.text("total"),
+            usage_cells(&room.usage).join(" | ")
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "\n**{0}:** {1}",
+        catalog.text("total"),
+        markdown_cell(&usage_line(&report.usage, catalog))
+    );
+    out
+}
+
+/// One row per socket; the totals are left to whoever reads the file.
+fn render_csv(report: &EnergyReport) -> String {
+    let mut out =
+        String::from("house,room,device_id,device,from,to,energy_kwh,peak_w,on_time_secs\n");
+
+    for room in &report.rooms {
+        for device in &room.devices {
+            let columns: Vec<String> = [
+                report.house.name.clone(),
+                room.name.clone(),
+                device.id.clone(),
+                device.name.clone(),
+                report.from.to_string(),
+                report.to.to_string(),
+                device.usage.energy_kwh.to_string(),
+                device.usage.peak_w.to_string(),
+                device.usage.on_time_secs.to_string(),
+            ]
+            .iter()
+            .map(|column| csv_field(column))
+            .collect();
+            let _ = writeln!(out, "{0}", columns.join(","));
+        }
+    }
+
+    out
+}
+
+fn render_html(report: &EnergyReport, catalog: &Catalog) -> String {
+    let title = html_escape(&title(report, catalog));
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"{0}\">\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<p>{1}</p>",
+        catalog.lang(),
+        html_escape(&period(report, catalog))
+    );
+
+    for room in &report.rooms {
+        let _ = writeln!(out, "<h2>{0}</h2>", html_escape(&room.name));
+        if room.devices.is_empty() {
+            let _ = writeln!(out, "<p>{0}</p>", catalog.text("no_sockets"));
+            continue;
+        }
+
+        let _ = writeln!(
+            out,
+            "<table>\n<tr><th>{0}</th></tr>",
+            columns(catalog).join("</th><th>")
+        );
+        for device in &room.devices {
+            let _ = writeln!(
+                out,
+                "<tr><td>{0}</td><td>{1}</td></tr>",
+                html_escape(&device.name),
+                usage_cells(&device.usage).join("</td><td>")
+            );
+        }
+        let _ = writeln!(
+            out,
+            "<tr><th>{0}</th><td>{1}</td></tr>\n</table>",
+            catalog.text("total"),
+            usage_cells(&room.usage).join("</td><td>")
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "<p><strong>{0}:</strong> {1}</p>",
+        catalog.text("total"),
+        html_escape(&usage_line(&report.usage, catalog))
+    );
+    out.push_str("</body>\n</html>\n");
+    out
+}
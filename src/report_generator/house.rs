@@ -0,0 +1,232 @@
+use super::Catalog;
+use crate::models::{DeviceReport, HouseReport, ReportFormat};
+use std::fmt::Write;
+
+/// Renders a house report in the requested format, with phrases from the catalogue.
+///
+/// JSON and CSV are meant for machines and don't depend on the language.
+pub fn render(report: &HouseReport, format: ReportFormat, catalog: &Catalog) -> String {
+    match format {
+        ReportFormat::Json => serde_json::to_string(report).unwrap_or_default(),
+        ReportFormat::Text => render_text(report, catalog),
+        ReportFormat::Markdown => render_markdown(report, catalog),
+        ReportFormat::Csv => render_csv(report),
+        ReportFormat::Html => render_html(report, catalog),
+    }
+}
+
+fn state<'a>(device: &DeviceReport, catalog: &'a Catalog) -> &'a str {
+    if device.device.state {
+        catalog.text("state_on")
+    } else {
+        catalog.text("state_off")
+    }
+}
+
+fn columns(catalog: &Catalog) -> [&str; 5] {
+    [
+        catalog.text("column_device"),
+        catalog.text("column_type"),
+        catalog.text("column_address"),
+        catalog.text("column_state"),
+        catalog.text("column_readings"),
+    ]
+}
+
+fn readings(device: &DeviceReport) -> String {
+    device
+        .readings
+        .iter()
+        .map(|reading| format!("{0} {1} {2}", reading.metric, reading.value, reading.unit))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn render_text(report: &HouseReport, catalog: &Catalog) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{0}",
+        catalog.format("house_report", &[("house", &report.house.name)])
+    );
+    if report.rooms.is_empty() {
+        let _ = writeln!(out, "{0}", catalog.text("no_rooms"));
+    }
+
+    for room in &report.rooms {
+        let _ = writeln!(
+            out,
+            "\n{0}",
+            catalog.format("room", &[("room", &room.room.name)])
+        );
+        if room.devices.is_empty() {
+            let _ = writeln!(out, "  {0}", catalog.text("no_devices"));
+        }
+        for device in &room.devices {
+            let _ = write!(
+                out,
+                "  - {0} ({1}, {2}): {3}",
+                device.device.name,
+                device.device.type_,
+                device.device.address.as_deref().unwrap_or("-"),
+                state(device, catalog)
+            );
+            if !device.readings.is_empty() {
+                let _ = write!(out, "; {0}", readings(device));
+            }
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Escapes the characters which would break a Markdown table cell.
+pub(super) fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+fn render_markdown(report: &HouseReport, catalog: &Catalog) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# {0}",
+        markdown_cell(&catalog.format("house_report", &[("house", &report.house.name)]))
+    );
+    if report.rooms.is_empty() {
+        let _ = writeln!(out, "\n{0}.", catalog.text("no_rooms"));
+    }
+
+    for room in &report.rooms {
+        let _ = writeln!(out, "\n## {0}\n", markdown_cell(&room.room.name));
+        if room.devices.is_empty() {
+            let _ = writeln!(out, "{0}.", catalog.text("no_devices"));
+            continue;
+        }
+
+        let _ = writeln!(out, "| {0} |", columns(catalog).join(" | "));
+        let _ = writeln!(out, "|---|---|---|---|---|");
+        for device in &room.devices {
+            let _ = writeln!(
+                out,
+                "| {0} | {1} | {2} | {3} | {4} |",
+                markdown_cell(&device.device.name),
+                device.device.type_,
+                markdown_cell(device.device.address.as_deref().unwrap_or("")),
+                state(device, catalog),
+                markdown_cell(&readings(device))
+            );
+        }
+    }
+
+    out
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+pub(super) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{0}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// One row per reading; devices without readings and empty rooms get a row
+/// with the missing columns left blank.
+fn render_csv(report: &HouseReport) -> String {
+    let mut out =
+        String::from("house,room,device_id,device,type,address,state,metric,value,unit\n");
+    let mut row = |room: &str, device: Option<&DeviceReport>, reading: [String; 3]| {
+        let device_columns = match device {
+            Some(device) => [
+                device.device.id.clone(),
+                device.device.name.clone(),
+                device.device.type_.to_string(),
+                device.device.address.clone().unwrap_or_default(),
+                device.device.state.to_string(),
+            ],
+            None => Default::default(),
+        };
+        let columns: Vec<String> = [report.house.name.clone(), room.to_owned()]
+            .into_iter()
+            .chain(device_columns)
+            .chain(reading)
+            .map(|column| csv_field(&column))
+            .collect();
+        let _ = writeln!(out, "{0}", columns.join(","));
+    };
+
+    for room in &report.rooms {
+        if room.devices.is_empty() {
+            row(&room.room.name, None, Default::default());
+        }
+        for device in &room.devices {
+            if device.readings.is_empty() {
+                row(&room.room.name, Some(device), Default::default());
+            }
+            for reading in &device.readings {
+                row(
+                    &room.room.name,
+                    Some(device),
+                    [
+                        reading.metric.to_string(),
+                        reading.value.to_string(),
+                        reading.unit.clone(),
+                    ],
+                );
+            }
+        }
+    }
+
+    out
+}
+
+pub(super) fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(report: &HouseReport, catalog: &Catalog) -> String {
+    let title = html_escape(&catalog.format("house_report", &[("house", &report.house.name)]));
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"{0}\">\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>",
+        catalog.lang()
+    );
+    if report.rooms.is_empty() {
+        let _ = writeln!(out, "<p>{0}</p>", catalog.text("no_rooms"));
+    }
+
+    for room in &report.rooms {
+        let _ = writeln!(out, "<h2>{0}</h2>", html_escape(&room.room.name));
+        if room.devices.is_empty() {
+            let _ = writeln!(out, "<p>{0}</p>", catalog.text("no_devices"));
+            continue;
+        }
+
+        let _ = writeln!(
+            out,
+            "<table>\n<tr><th>{0}</th></tr>",
+            columns(catalog).join("</th><th>")
+        );
+        for device in &room.devices {
+            let _ = writeln!(
+                out,
+                "<tr><td>{0}</td><td>{1}</td><td>{2}</td><td>{3}</td><td>{4}</td></tr>",
+                html_escape(&device.device.name),
+                device.device.type_,
+                html_escape(device.device.address.as_deref().unwrap_or("")),
+                state(device, catalog),
+                html_escape(&readings(device))
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Language used when the client asks for none of the known ones.
+pub const DEFAULT_LANGUAGE: &str = "ru";
+
+/// Report phrases by language and message key.
+///
+/// Adding a language only takes a new entry in `locales.json`; keys missing
+/// from it fall back to the default language.
+static CATALOGS: OnceLock<HashMap<String, Catalog>> = OnceLock::new();
+
+fn catalogs() -> &'static HashMap<String, Catalog> {
+    CATALOGS.get_or_init(|| {
+        let all: HashMap<String, HashMap<String, String>> =
+            serde_json::from_str(include_str!("locales.json"))
+                .expect("report catalogues should be valid JSON");
+
+        all.into_iter()
+            .map(|(lang, messages)| (lang.clone(), Catalog { lang, messages }))
+            .collect()
+    })
+}
+
+/// Report phrases of one language.
+#[derive(Debug)]
+pub struct Catalog {
+    lang: String,
+    messages: HashMap<String, String>,
+}
+
+#[derive(Debug)]
+pub struct UnknownLanguage(pub String);
+
+impl fmt::Display for UnknownLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unsupported language: {0}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownLanguage {}
+
+impl Catalog {
+    /// Catalogue of the default language.
+    pub fn default_language() -> &'static Catalog {
+        &catalogs()[DEFAULT_LANGUAGE]
+    }
+
+    /// Catalogue of a language tag such as `en` or `en-GB`.
+    pub fn find(tag: &str) -> Option<&'static Catalog> {
+        let primary = tag.split(['-', '_']).next().unwrap_or(tag);
+        catalogs().get(&primary.to_lowercase())
+    }
+
+    /// Catalogue of a language requested explicitly, e.g. by a `lang` parameter.
+    pub fn get(tag: &str) -> Result<&'static Catalog, UnknownLanguage> {
+        Self::find(tag).ok_or_else(|| UnknownLanguage(tag.to_owned()))
+    }
+
+    /// First known catalogue out of languages in order of preference.
+    pub fn negotiate<'a>(tags: impl IntoIterator<Item = &'a str>) -> &'static Catalog {
+        tags.into_iter()
+            .find_map(Self::find)
+            .unwrap_or_else(Self::default_language)
+    }
+
+    /// Language tag of the catalogue.
+    pub fn lang(&self) -> &str {
+        &self.lang
+    }
+
+    /// Phrase for a message key; unknown keys are returned as they are.
+    pub fn text<'a>(&'a self, key: &'a str) -> &'a str {
+        self.messages
+            .get(key)
+            .or_else(|| Self::default_language().messages.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// Phrase for a message key with `{name}` placeholders filled in.
+    pub fn format(&self, key: &str, args: &[(&str, &str)]) -> String {
+        let mut out = String::new();
+        let mut rest = self.text(key);
+
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let arg = after.find('}').and_then(|end| {
+                let value = args.iter().find(|(name, _)| *name == &after[..end])?.1;
+                Some((value, end))
+            });
+            match arg {
+                Some((value, end)) => {
+                    out.push_str(value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = after;
+                }
+            }
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
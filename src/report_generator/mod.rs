@@ -1,6 +1,14 @@
 use crate::models::Item;
 use std::{collections::HashMap, fmt, string::ParseError};
 
+mod energy;
+mod house;
+mod locale;
+
+pub use energy::render as render_energy;
+pub use house::render;
+pub use locale::{Catalog, UnknownLanguage, DEFAULT_LANGUAGE};
+
 #[derive(Debug)]
 pub enum DoubleError {
     EmptyVec,
@@ -18,36 +26,19 @@ impl fmt::Display for DoubleError {
     }
 }
 
-pub fn generate_report<U: Item>(data: Vec<U>) -> Result<String, DoubleError> {
-    let _l: usize = match data.len() {
-        0 => return Err(DoubleError::EmptyVec),
-        n => n,
-    };
-    let item_type: &str = std::any::type_name::<U>()
-        .split("::")
-        .collect::<Vec<&str>>()[2];
-
-    let mut report: String = String::from("");
-    report.push_str(&format!("Отчет для устройств {0}:", item_type));
-    for item in data {
-        report.push_str(&format!(" Имя {0}, id {1}", item.name(), item.id()));
-    }
-    Ok(report)
+/// Plural label of the kind of `U` in the catalogue's language.
+fn kind_label<U: Item>(catalog: &Catalog) -> String {
+    catalog.format(&format!("kind_{0}", U::KIND), &[])
 }
 
-pub fn generate_report_id<U: Item>(data: Vec<U>) -> Result<String, DoubleError> {
-    let _l: usize = match data.len() {
-        0 => return Err(DoubleError::EmptyVec),
-        n => n,
-    };
-    let item_type: &str = std::any::type_name::<U>()
-        .split("::")
-        .collect::<Vec<&str>>()[2];
+pub fn generate_report_id<U: Item>(data: Vec<U>, catalog: &Catalog) -> Result<String, DoubleError> {
+    if data.is_empty() {
+        return Err(DoubleError::EmptyVec);
+    }
 
-    let mut report: String = String::from("");
-    report.push_str(&format!("Список id для устройств {0}:", item_type));
+    let mut report = catalog.format("items_ids", &[("kind", &kind_label::<U>(catalog))]);
     for item in data {
-        report.push_str(&format!(" id {0}", item.id()));
+        report.push_str(&format!(" {0} {1}", catalog.text("field_id"), item.id()));
     }
     Ok(report)
 }
@@ -77,3 +68,225 @@ pub fn generate_name_id<U: Item>(data: Vec<U>) -> Result<HashMap<String, String>
     }
     Ok(report)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        Device, DeviceEnergy, DeviceKind, DeviceReport, EnergyReport, EnergyUsage, House,
+        HouseReport, Metric, Reading, ReportFormat, Room, RoomEnergy, RoomReport,
+    };
+
+    fn russian() -> &'static Catalog {
+        Catalog::get("ru").unwrap()
+    }
+
+    fn english() -> &'static Catalog {
+        Catalog::get("en").unwrap()
+    }
+
+    fn house() -> House {
+        House {
+            id: String::from("h1"),
+            name: String::from("Home"),
+            deleted_at: None,
+        }
+    }
+
+    fn room(id: &str, name: &str) -> Room {
+        Room {
+            id: String::from(id),
+            name: String::from(name),
+            house: String::from("h1"),
+            deleted_at: None,
+        }
+    }
+
+    fn device(id: &str, name: &str) -> Device {
+        Device {
+            id: String::from(id),
+            name: String::from(name),
+            type_: DeviceKind::Socket,
+            address: Some(String::from("10.0.0.2")),
+            state: true,
+            variable: 0,
+            room: String::from("r1"),
+            version: 1,
+            deleted_at: None,
+        }
+    }
+
+    fn house_report() -> HouseReport {
+        let kettle = DeviceReport {
+            device: device("d1", "Kettle, <big>"),
+            readings: vec![Reading {
+                device: String::from("d1"),
+                metric: Metric::Power,
+                value: 1500.0,
+                unit: String::from("W"),
+                updated_at: 0,
+            }],
+        };
+
+        HouseReport {
+            house: house(),
+            rooms: vec![
+                RoomReport {
+                    room: room("r2", "Attic"),
+                    devices: Vec::new(),
+                },
+                RoomReport {
+                    room: room("r1", "Kitchen"),
+                    devices: vec![kettle],
+                },
+            ],
+            generated_at: 0,
+        }
+    }
+
+    #[test]
+    fn generate_report_id_lists_ids() {
+        let report = generate_report_id(vec![house()], english()).unwrap();
+        assert_eq!(report, "Ids of houses: id h1");
+
+        assert!(generate_report_id(Vec::<Room>::new(), english()).is_err());
+    }
+
+    #[test]
+    fn generate_list_id_keeps_trailing_separator() {
+        let ids = generate_list_id(vec![device("d1", "Lamp"), device("d2", "Fan")]).unwrap();
+        assert_eq!(ids, ["d1 ", "d2 "]);
+
+        assert!(generate_list_id(Vec::<Device>::new()).is_err());
+    }
+
+    #[test]
+    fn generate_name_id_maps_names() {
+        let names = generate_name_id(vec![room("r1", "Kitchen"), room("r2", "Attic")]).unwrap();
+        assert_eq!(names["Kitchen"], "r1");
+        assert_eq!(names["Attic"], "r2");
+
+        assert!(generate_name_id(Vec::<Room>::new()).is_err());
+    }
+
+    #[test]
+    fn render_text_lists_rooms_and_devices() {
+        let text = render(&house_report(), ReportFormat::Text, english());
+        assert!(text.starts_with("Report on house Home\n"));
+        assert!(text.contains("Room Attic\n  No devices\n"));
+        assert!(text.contains("  - Kettle, <big> (socket, 10.0.0.2): on; power 1500 W\n"));
+
+        let empty = HouseReport {
+            rooms: Vec::new(),
+            ..house_report()
+        };
+        assert!(render(&empty, ReportFormat::Text, russian()).contains("В доме нет комнат"));
+    }
+
+    #[test]
+    fn render_markdown_builds_tables() {
+        let markdown = render(&house_report(), ReportFormat::Markdown, russian());
+        assert!(markdown.starts_with("# Отчет по дому Home\n"));
+        assert!(markdown.contains("## Attic\n\nНет устройств.\n"));
+        assert!(markdown.contains("| Устройство | Тип | Адрес | Состояние | Показания |"));
+        assert!(
+            markdown.contains("| Kettle, <big> | socket | 10.0.0.2 | включено | power 1500 W |")
+        );
+    }
+
+    #[test]
+    fn render_csv_quotes_fields() {
+        let csv = render(&house_report(), ReportFormat::Csv, english());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "house,room,device_id,device,type,address,state,metric,value,unit",
+                "Home,Attic,,,,,,,,",
+                "Home,Kitchen,d1,\"Kettle, <big>\",socket,10.0.0.2,true,power,1500,W",
+            ]
+        );
+    }
+
+    #[test]
+    fn render_html_escapes_names() {
+        let html = render(&house_report(), ReportFormat::Html, english());
+        assert!(html.contains("<html lang=\"en\">"));
+        assert!(html.contains("<td>Kettle, &lt;big&gt;</td>"));
+        assert!(html.contains("<h2>Attic</h2>\n<p>No devices</p>"));
+    }
+
+    #[test]
+    fn render_json_keeps_structure() {
+        let json = render(&house_report(), ReportFormat::Json, english());
+        let report: HouseReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(report.rooms.len(), 2);
+        assert_eq!(report.rooms[1].devices[0].readings[0].value, 1500.0);
+    }
+
+    #[test]
+    fn render_energy_totals_rooms() {
+        let usage = EnergyUsage {
+            energy_kwh: 1.25,
+            peak_w: 1800.0,
+            on_time_secs: 2700,
+        };
+        let report = EnergyReport {
+            house: house(),
+            from: 0,
+            to: 3600,
+            usage,
+            rooms: vec![
+                RoomEnergy {
+                    id: String::from("r2"),
+                    name: String::from("Attic"),
+                    usage: EnergyUsage::default(),
+                    devices: Vec::new(),
+                },
+                RoomEnergy {
+                    id: String::from("r1"),
+                    name: String::from("Kitchen"),
+                    usage,
+                    devices: vec![DeviceEnergy {
+                        id: String::from("d1"),
+                        name: String::from("Kettle, <big>"),
+                        usage,
+                    }],
+                },
+            ],
+        };
+
+        let text = render_energy(&report, ReportFormat::Text, english());
+        assert!(text.starts_with(
+            "Energy use of house Home\nPeriod: 1970-01-01 00:00 UTC – 1970-01-01 01:00 UTC\n"
+        ));
+        assert!(text.contains("Room Attic: 0.000 kWh, 0.0 W, 0:00:00\n  No sockets\n"));
+        assert!(text.contains("  - Kettle, <big>: 1.250 kWh, 1800.0 W, 0:45:00\n"));
+
+        let markdown = render_energy(&report, ReportFormat::Markdown, russian());
+        assert!(markdown.contains("| Kettle, <big> | 1.250 | 1800.0 | 0:45:00 |"));
+        assert!(markdown.contains("**Итого:** 1.250 кВт·ч, 1800.0 Вт, 0:45:00"));
+
+        let csv = render_energy(&report, ReportFormat::Csv, english());
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            [
+                "house,room,device_id,device,from,to,energy_kwh,peak_w,on_time_secs",
+                "Home,Kitchen,d1,\"Kettle, <big>\",0,3600,1.25,1800,2700",
+            ]
+        );
+    }
+
+    #[test]
+    fn catalog_falls_back() {
+        assert_eq!(Catalog::negotiate(["de", "en"]).lang(), "en");
+        assert_eq!(Catalog::negotiate(["de"]).lang(), DEFAULT_LANGUAGE);
+        assert_eq!(Catalog::find("en-GB").unwrap().lang(), "en");
+        assert!(Catalog::get("xx").is_err());
+        assert_eq!(english().text("missing_key"), "missing_key");
+        assert_eq!(
+            english().format("room", &[("room", "{house}")]),
+            "Room {house}"
+        );
+    }
+}
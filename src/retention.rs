@@ -0,0 +1,110 @@
+use crate::{actions, DbPool};
+use actix_web::{rt, web};
+use std::time::Duration;
+
+/// How long reading history and deleted entities are kept.
+///
+/// Read from the environment:
+/// - `HISTORY_RETENTION_DAYS`: drop samples older than this many days (default 30, 0 disables)
+/// - `HISTORY_MAX_SAMPLES`: keep at most this many samples per device metric (unset disables)
+/// - `TRASH_RETENTION_DAYS`: purge entities deleted this many days ago (default 30, 0 disables)
+/// - `HISTORY_RETENTION_INTERVAL_SECS`: how often the policy is applied (default 3600)
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub max_age: Option<Duration>,
+    pub max_samples: Option<i64>,
+    pub trash_max_age: Option<Duration>,
+    pub interval: Duration,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+            max_samples: None,
+            trash_max_age: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+            interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+
+        if let Some(days) = env_number::<u64>("HISTORY_RETENTION_DAYS") {
+            policy.max_age = (days > 0).then(|| Duration::from_secs(days * 24 * 60 * 60));
+        }
+        if let Some(samples) = env_number::<i64>("HISTORY_MAX_SAMPLES") {
+            policy.max_samples = (samples > 0).then_some(samples);
+        }
+        if let Some(days) = env_number::<u64>("TRASH_RETENTION_DAYS") {
+            policy.trash_max_age = (days > 0).then(|| Duration::from_secs(days * 24 * 60 * 60));
+        }
+        if let Some(secs) = env_number::<u64>("HISTORY_RETENTION_INTERVAL_SECS") {
+            policy.interval = Duration::from_secs(secs.max(1));
+        }
+
+        policy
+    }
+}
+
+fn env_number<T: std::str::FromStr>(key: &str) -> Option<T> {
+    let value = std::env::var(key).ok()?;
+    match value.parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            log::warn!("ignoring {key}={value}: not a number");
+            None
+        }
+    }
+}
+
+/// Periodically applies the retention policy to the reading history and the trash.
+pub fn spawn(pool: DbPool, policy: RetentionPolicy) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(policy.interval);
+
+        loop {
+            interval.tick().await;
+
+            remove_expired_history(pool.clone(), policy.clone()).await;
+            if let Some(max_age) = policy.trash_max_age {
+                purge_trash(pool.clone(), max_age).await;
+            }
+        }
+    });
+}
+
+async fn remove_expired_history(pool: DbPool, policy: RetentionPolicy) {
+    let removed = web::block(move || {
+        let mut conn = pool.get()?;
+
+        actions::apply_history_retention(&mut conn, &policy)
+    })
+    .await;
+
+    match removed {
+        Ok(Ok(0)) => {}
+        Ok(Ok(removed)) => log::info!("removed {removed} expired history samples"),
+        Ok(Err(e)) => log::error!("history retention failed: {e}"),
+        Err(e) => log::error!("history retention failed: {e}"),
+    }
+}
+
+async fn purge_trash(pool: DbPool, max_age: Duration) {
+    let purged = web::block(move || {
+        let mut conn = pool.get()?;
+
+        let cutoff = actions::unix_now() - max_age.as_secs() as i64;
+        actions::purge_trash(&mut conn, cutoff)
+    })
+    .await;
+
+    match purged {
+        Ok(Ok(0)) => {}
+        Ok(Ok(purged)) => log::info!("purged {purged} deleted entities from the trash"),
+        Ok(Err(e)) => log::error!("purging the trash failed: {e}"),
+        Err(e) => log::error!("purging the trash failed: {e}"),
+    }
+}
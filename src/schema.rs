@@ -10,6 +10,22 @@ diesel::table! {
         state -> Bool,
         variable -> Integer,
         room -> Text,
+        version -> Integer,
+        deleted_at -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    events (id) {
+        id -> Integer,
+        at -> BigInt,
+        action -> Text,
+        entity -> Text,
+        entity_id -> Text,
+        house -> Text,
+        client -> Text,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
     }
 }
 
@@ -17,6 +33,28 @@ diesel::table! {
     houses (id) {
         id -> Text,
         name -> Text,
+        deleted_at -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    reading_history (id) {
+        id -> Integer,
+        device -> Text,
+        metric -> Text,
+        value -> Double,
+        unit -> Text,
+        recorded_at -> BigInt,
+    }
+}
+
+diesel::table! {
+    readings (device, metric) {
+        device -> Text,
+        metric -> Text,
+        value -> Double,
+        unit -> Text,
+        updated_at -> BigInt,
     }
 }
 
@@ -25,10 +63,31 @@ diesel::table! {
         id -> Text,
         name -> Text,
         house -> Text,
+        deleted_at -> Nullable<BigInt>,
+    }
+}
+
+diesel::table! {
+    state_changes (id) {
+        id -> Integer,
+        device -> Text,
+        state -> Bool,
+        changed_at -> BigInt,
     }
 }
 
 diesel::joinable!(devices -> rooms (room));
+diesel::joinable!(reading_history -> devices (device));
+diesel::joinable!(readings -> devices (device));
 diesel::joinable!(rooms -> houses (house));
+diesel::joinable!(state_changes -> devices (device));
 
-diesel::allow_tables_to_appear_in_same_query!(devices, houses, rooms,);
+diesel::allow_tables_to_appear_in_same_query!(
+    devices,
+    events,
+    houses,
+    reading_history,
+    readings,
+    rooms,
+    state_changes,
+);
@@ -0,0 +1,241 @@
+use crate::error::AppError;
+use crate::live::{ChangeHub, Publish};
+use crate::models::{self, Metric, NewReading};
+use crate::{actions, DbPool};
+use actix::Addr;
+use actix_web::rt::{self, net::UdpSocket};
+use actix_web::web;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Length of a binary datagram: the device UUID and the temperature.
+pub const BINARY_LEN: usize = 20;
+
+/// Longest datagram read; anything longer is malformed.
+const MAX_DATAGRAM: usize = 1024;
+
+/// Where thermometers send their readings.
+///
+/// Read from the environment:
+/// - `UDP_BIND`: address the listener binds to (default `127.0.0.1:7990`, empty disables it)
+#[derive(Debug, Clone)]
+pub struct UdpConfig {
+    pub bind: Option<String>,
+}
+
+impl Default for UdpConfig {
+    fn default() -> Self {
+        Self {
+            bind: Some(String::from("127.0.0.1:7990")),
+        }
+    }
+}
+
+impl UdpConfig {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(bind) = std::env::var("UDP_BIND") {
+            let bind = bind.trim();
+            config.bind = (!bind.is_empty()).then(|| bind.to_owned());
+        }
+
+        config
+    }
+}
+
+/// Counts of the datagrams received, shared with `GET /metrics`.
+#[derive(Debug, Default)]
+pub struct Counters {
+    received: AtomicU64,
+    accepted: AtomicU64,
+    malformed: AtomicU64,
+    unknown_device: AtomicU64,
+    rejected: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl Counters {
+    pub fn snapshot(&self) -> models::UdpMetrics {
+        models::UdpMetrics {
+            received: self.received.load(Ordering::Relaxed),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            malformed: self.malformed.load(Ordering::Relaxed),
+            unknown_device: self.unknown_device.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Temperature reported by a thermometer.
+///
+/// A datagram is either JSON, e.g. `{"device": "<uuid>", "temperature": 21.5, "unit": "°C"}`
+/// with an optional unit, or [`BINARY_LEN`] bytes: the 16 bytes of the device UUID
+/// followed by the temperature in °C as a big-endian `f32`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Datagram {
+    pub device: Uuid,
+    pub temperature: f64,
+    pub unit: Option<String>,
+}
+
+impl Datagram {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.trim_ascii_start().starts_with(b"{") {
+            return serde_json::from_slice(bytes).ok();
+        }
+        if bytes.len() != BINARY_LEN {
+            return None;
+        }
+
+        let (device, temperature) = bytes.split_at(16);
+        Some(Self {
+            device: Uuid::from_slice(device).ok()?,
+            temperature: f64::from(f32::from_be_bytes(temperature.try_into().ok()?)),
+            unit: None,
+        })
+    }
+}
+
+/// Stores a datagram as a temperature reading of its device.
+///
+/// Returns `None` when the device doesn't exist.
+pub fn store(
+    conn: &mut diesel::SqliteConnection,
+    datagram: &Datagram,
+    actor: &models::Actor,
+) -> Result<Option<Vec<models::Reading>>, AppError> {
+    let reading = NewReading {
+        metric: Metric::Temperature,
+        value: datagram.temperature,
+        unit: datagram.unit.clone(),
+        recorded_at: None,
+    };
+
+    actions::record_readings(conn, datagram.device, &[reading], actor)
+}
+
+/// Receives datagrams on the socket, storing the readings and pushing them to
+/// live subscribers.
+pub async fn listen(
+    socket: UdpSocket,
+    pool: DbPool,
+    counters: Arc<Counters>,
+    hub: Addr<ChangeHub>,
+) {
+    let mut buf = [0u8; MAX_DATAGRAM + 1];
+
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                log::warn!("receiving a UDP datagram failed: {e}");
+                continue;
+            }
+        };
+        Counters::count(&counters.received);
+
+        let Some(datagram) = (len <= MAX_DATAGRAM)
+            .then(|| Datagram::parse(&buf[..len]))
+            .flatten()
+        else {
+            log::debug!("malformed UDP datagram from {peer}");
+            Counters::count(&counters.malformed);
+            continue;
+        };
+
+        let pool = pool.clone();
+        let stored = web::block(move || {
+            let mut conn = pool.get()?;
+            let actor = models::Actor::new(peer.ip().to_string());
+
+            store(&mut conn, &datagram, &actor).map(|stored| (stored, datagram, actor))
+        })
+        .await;
+
+        match stored {
+            Ok(Ok((Some(_), _, actor))) => {
+                Counters::count(&counters.accepted);
+                hub.do_send(Publish(actor.take_changes()));
+            }
+            Ok(Ok((None, datagram, _))) => {
+                log::debug!(
+                    "UDP reading from {peer} for unknown device {0}",
+                    datagram.device
+                );
+                Counters::count(&counters.unknown_device);
+            }
+            Ok(Err(AppError::Validation(reason))) => {
+                log::debug!("rejected UDP reading from {peer}: {reason}");
+                Counters::count(&counters.rejected);
+            }
+            Ok(Err(e)) => {
+                log::error!("storing a UDP reading failed: {e}");
+                Counters::count(&counters.failed);
+            }
+            Err(e) => {
+                log::error!("storing a UDP reading failed: {e}");
+                Counters::count(&counters.failed);
+            }
+        }
+    }
+}
+
+/// Starts the listener if an address is configured.
+pub fn spawn(pool: DbPool, config: UdpConfig, counters: Arc<Counters>, hub: Addr<ChangeHub>) {
+    let Some(bind) = config.bind else {
+        return;
+    };
+
+    rt::spawn(async move {
+        match UdpSocket::bind(&bind).await {
+            Ok(socket) => {
+                log::info!("receiving thermometer readings on udp://{bind}");
+                listen(socket, pool, counters, hub).await;
+            }
+            Err(e) => log::error!("binding the UDP listener to {bind} failed: {e}"),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_datagrams() {
+        let device = Uuid::new_v4();
+
+        let json = format!(r#" {{"device": "{device}", "temperature": 21.5, "unit": "°F"}}"#);
+        assert_eq!(
+            Datagram::parse(json.as_bytes()),
+            Some(Datagram {
+                device,
+                temperature: 21.5,
+                unit: Some(String::from("°F")),
+            })
+        );
+
+        let mut binary = device.as_bytes().to_vec();
+        binary.extend_from_slice(&(-4.25f32).to_be_bytes());
+        assert_eq!(
+            Datagram::parse(&binary),
+            Some(Datagram {
+                device,
+                temperature: -4.25,
+                unit: None,
+            })
+        );
+
+        assert_eq!(Datagram::parse(&binary[..19]), None);
+        assert_eq!(Datagram::parse(b"{\"device\": \"kitchen\"}"), None);
+        assert_eq!(Datagram::parse(b""), None);
+    }
+}